@@ -1,7 +1,7 @@
 use Extract::*;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use regex::Regex;
-use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use csv::{ReaderBuilder, WriterBuilder};
 use std::{
     error::Error,
     ops::Range,
@@ -12,32 +12,99 @@ use std::{
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 type PositionList = Vec<Range<usize>>;
+type FieldList = Vec<FieldItem>;
+
+/// An item of a list of fields
+#[derive(Debug, Clone)]
+pub enum FieldItem {
+    Range(Range<usize>),
+    /// A quoted value in the list, output as a field of its own
+    Literal(String),
+}
+
+/// The order in which selected positions are output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Order {
+    /// In input order, each once, as cut does
+    Input,
+    /// As listed, with any repeats
+    AsGiven,
+}
 
 #[derive(Debug)]
 pub enum Extract {
-    Fields(PositionList),
+    Fields(FieldList),
+    /// Fields named in the header row, as names, ranges of names or globs
+    Headers {
+        names: Vec<String>,
+        order: Order,
+        complement: bool,
+    },
     Bytes(PositionList),
     Chars(PositionList),
 }
 
+/// How lines are split into fields, other than as CSV
+#[derive(Debug)]
+pub enum Split {
+    /// On each delimiter, as cut does
+    Delimiter(char),
+    /// On runs of whitespace, ignoring any at the start or end of a line
+    Whitespace,
+    /// On each match of a regular expression
+    Regex(Regex),
+}
+
+impl Split {
+    fn fields<'a>(&self, line: &'a str) -> Vec<&'a str> {
+        match self {
+            Split::Delimiter(delim) => line.split(*delim).collect(),
+            Split::Whitespace => line.split_whitespace().collect(),
+            Split::Regex(re) => re.split(line).collect(),
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 pub struct ConfigArgs {
     #[arg(name = "FILE", default_value = "-", help = "Input file(s)")]
     files: Vec<String>,
     #[arg(short, long = "delim", default_value = "\t", help = "Field delimiter")]
     delimiter: String,
-    #[arg(short, long, help = "Select fields", conflicts_with_all(&["bytes", "chars"]))]
+    #[arg(short, long, help = "Select fields; with --order=as-given, items quoted or of only punctuation (not -*?[) are output as literals", allow_hyphen_values = true, conflicts_with_all(&["bytes", "chars"]))]
     fields: Option<String>,
-    #[arg(short, long, help = "Select bytes", conflicts_with_all(&["fields", "chars"]))]
+    #[arg(short, long, help = "Select bytes", allow_hyphen_values = true, conflicts_with_all(&["fields", "chars"]))]
     bytes: Option<String>,
-    #[arg(short, long, help = "Select characters", conflicts_with_all(&["fields", "bytes"]))]
+    #[arg(short, long, help = "Select characters", allow_hyphen_values = true, conflicts_with_all(&["fields", "bytes"]))]
     chars: Option<String>,
+    #[arg(short = 'F', long, value_name = "NAMES", help = "Select fields by their names in the header row, with literals as for --fields", conflicts_with_all(&["fields", "bytes", "chars"]))]
+    header_fields: Option<String>,
+    #[arg(long, help = "Select all but the given bytes, characters or fields")]
+    complement: bool,
+    #[arg(long, value_enum, default_value_t = Order::Input, help = "Output order of the selected bytes, characters or fields")]
+    order: Order,
+    #[arg(long, value_name = "STRING", help = "Output delimiter [default: the field delimiter]")]
+    output_delimiter: Option<String>,
+    #[arg(short = 's', long, help = "Skip lines without a field delimiter")]
+    only_delimited: bool,
+    #[arg(short, long, help = "Split fields on runs of whitespace", conflicts_with_all(&["delimiter", "delim_regex", "csv"]))]
+    whitespace: bool,
+    #[arg(long, value_name = "REGEX", help = "Split fields on a regular expression", conflicts_with_all(&["delimiter", "csv"]))]
+    delim_regex: Option<Regex>,
+    #[arg(long, help = "Read and write fields as CSV, with quoting", overrides_with = "no_quote")]
+    csv: bool,
+    #[arg(long, help = "Split fields on every delimiter and write them verbatim [default]", overrides_with = "csv")]
+    no_quote: bool,
 }
 
 #[derive(Debug)]
 pub struct Config {
     files: Vec<String>,
     delimiter: u8,
+    output_delimiter: Option<String>,
+    only_delimited: bool,
+    /// How fields are split, or `None` to read them as CSV
+    split: Option<Split>,
     extract: Extract,
 }
 
@@ -54,32 +121,152 @@ fn parse_index(input: &str) -> Result<usize, String> {
         })
 }
 
+fn parse_range(val: &str, range_re: &Regex) -> Result<Range<usize>, String> {
+    parse_index(val).map(|n| n..n + 1).or_else(|e| {
+        range_re.captures(val).ok_or(e).and_then(|captures| {
+            let n1 = captures.get(1).map(|m| parse_index(m.as_str()));
+            let n2 = captures.get(2).map(|m| parse_index(m.as_str()));
+            match (n1.transpose()?, n2.transpose()?) {
+                (Some(n1), Some(n2)) if n1 >= n2 => Err(format!(
+                    "First number in range ({}) \
+                    must be lower than second number ({})",
+                    n1 + 1,
+                    n2 + 1
+                )),
+                (Some(n1), Some(n2)) => Ok(n1..n2 + 1),
+                (Some(n1), None) => Ok(n1..usize::MAX),
+                (None, Some(n2)) => Ok(0..n2 + 1),
+                (None, None) => {
+                    Err(format!("illegal list value: \"{}\"", val))
+                }
+            }
+        })
+    })
+}
+
 fn parse_pos(range: &str) -> MyResult<PositionList> {
-    let range_re = Regex::new(r"^(\d+)-(\d+)$").unwrap();
+    let range_re = Regex::new(r"^(\d+)?-(\d+)?$").unwrap();
     range
         .split(',')
+        .map(|val| parse_range(val, &range_re))
+        .collect::<Result<_, _>>()
+        .map_err(From::from)
+}
+
+/// Splits a list on the commas that are not quoted
+fn split_list(list: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut start = 0;
+    let mut quote = None;
+    for (i, c) in list.char_indices() {
+        match (c, quote) {
+            (_, Some(q)) if c == q => quote = None,
+            ('\'' | '"', None) => quote = Some(c),
+            (',', None) => {
+                items.push(&list[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&list[start..]);
+    items
+}
+
+/// The value of a list item that is output as a literal: one in single or
+/// double quotes or, as the shell strips quotes unless they are quoted
+/// themselves, one of only spaces and punctuation other than the `-` of
+/// ranges and the `*?[` of globs, such as `=` or ` | `
+fn literal(item: &str) -> Option<&str> {
+    let quoted = ['\'', '"']
         .into_iter()
-        .map(|val| {
-            parse_index(val).map(|n| n..n + 1).or_else(|e| {
-                range_re.captures(val).ok_or(e).and_then(|captures| {
-                    let n1 = parse_index(&captures[1])?;
-                    let n2 = parse_index(&captures[2])?;
-                    if n1 >= n2 {
-                        return Err(format!(
-                            "First number in range ({}) \
-                            must be lower than second number ({})",
-                            n1 + 1,
-                            n2 + 1
-                        ));
-                    }
-                    Ok(n1..n2 + 1)
-                })
-            })
+        .find_map(|q| item.strip_prefix(q)?.strip_suffix(q));
+    let bare = !item.is_empty()
+        && !item.contains(|c: char| c.is_alphanumeric() || "-*?[".contains(c));
+    quoted.or(bare.then_some(item))
+}
+
+fn parse_fields(list: &str) -> MyResult<FieldList> {
+    let range_re = Regex::new(r"^(\d+)?-(\d+)?$").unwrap();
+    split_list(list)
+        .into_iter()
+        .map(|val| match literal(val) {
+            Some(value) => Ok(FieldItem::Literal(value.to_string())),
+            None => parse_range(val, &range_re).map(FieldItem::Range),
         })
         .collect::<Result<_, _>>()
         .map_err(From::from)
 }
 
+/// Sorts ranges and joins those that overlap, so each position is
+/// selected once and in input order
+fn merge(mut positions: PositionList) -> PositionList {
+    positions.sort_by_key(|range| range.start);
+    let mut merged: PositionList = vec![];
+    for range in positions {
+        match merged.last_mut() {
+            Some(last) if range.start < last.end => {
+                last.end = last.end.max(range.end);
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// The positions not in merged ranges
+fn complement(positions: &[Range<usize>]) -> PositionList {
+    let mut start = 0;
+    let mut gaps = vec![];
+    for range in positions {
+        if range.start > start {
+            gaps.push(start..range.start);
+        }
+        start = range.end;
+    }
+    if start < usize::MAX {
+        gaps.push(start..usize::MAX);
+    }
+    gaps
+}
+
+/// Puts positions in input order, each once, unless they are kept as
+/// given, or selects all the others instead
+fn arrange(positions: PositionList, order: Order, others: bool) -> PositionList {
+    if others {
+        complement(&merge(positions))
+    } else if order == Order::AsGiven {
+        positions
+    } else {
+        merge(positions)
+    }
+}
+
+/// Arranges fields like positions, keeping literals only as given
+fn arrange_fields(
+    fields: FieldList,
+    order: Order,
+    others: bool,
+) -> MyResult<FieldList> {
+    if order == Order::AsGiven && !others {
+        return Ok(fields);
+    }
+    let positions = fields
+        .into_iter()
+        .map(|field| match field {
+            FieldItem::Range(range) => Ok(range),
+            FieldItem::Literal(value) => Err(format!(
+                "literal \"{}\" needs --order=as-given without --complement",
+                value
+            )),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(arrange(positions, order, others)
+        .into_iter()
+        .map(FieldItem::Range)
+        .collect())
+}
+
 pub fn get_args() -> MyResult<Config> {
     let args = ConfigArgs::parse();
     let delimiter = args.delimiter.as_bytes();
@@ -89,11 +276,33 @@ pub fn get_args() -> MyResult<Config> {
             args.delimiter
         )));
     }
-    let fields = args.fields.as_deref().map(parse_pos).transpose()?;
-    let bytes = args.bytes.as_deref().map(parse_pos).transpose()?;
-    let chars = args.chars.as_deref().map(parse_pos).transpose()?;
+    let parse = |list: &str| {
+        parse_pos(list)
+            .map(|positions| arrange(positions, args.order, args.complement))
+    };
+    let fields = args
+        .fields
+        .as_deref()
+        .map(|list| {
+            arrange_fields(parse_fields(list)?, args.order, args.complement)
+        })
+        .transpose()?;
+    let bytes = args.bytes.as_deref().map(parse).transpose()?;
+    let chars = args.chars.as_deref().map(parse).transpose()?;
     let extract = if let Some(fields) = fields {
         Fields(fields)
+    } else if let Some(names) = &args.header_fields {
+        Headers {
+            names: split_list(names)
+                .into_iter()
+                .map(|name| match name {
+                    "" => Err(format!("illegal list value: \"{}\"", name)),
+                    _ => Ok(name.to_string()),
+                })
+                .collect::<Result<_, _>>()?,
+            order: args.order,
+            complement: args.complement,
+        }
     } else if let Some(bytes) = bytes {
         Bytes(bytes)
     } else if let Some(chars) = chars {
@@ -101,9 +310,33 @@ pub fn get_args() -> MyResult<Config> {
     } else {
         return Err(From::from("Must have --fields, --bytes, or --chars"));
     };
+    let split = if args.whitespace {
+        Some(Split::Whitespace)
+    } else if let Some(re) = args.delim_regex {
+        Some(Split::Regex(re))
+    } else if args.csv {
+        None
+    } else {
+        Some(Split::Delimiter(char::from(delimiter[0])))
+    };
+    if !matches!(extract, Fields(_) | Headers { .. }) {
+        if args.only_delimited {
+            return Err(From::from(
+                "--only-delimited only makes sense with --fields",
+            ));
+        }
+        if matches!(split, Some(Split::Whitespace | Split::Regex(_))) {
+            return Err(From::from(
+                "--whitespace and --delim-regex only make sense with --fields",
+            ));
+        }
+    }
     Ok(Config {
         files: args.files,
         delimiter: delimiter[0],
+        output_delimiter: args.output_delimiter,
+        only_delimited: args.only_delimited,
+        split,
         extract,
     })
 }
@@ -115,68 +348,245 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
-fn extract_chars(line: &str, char_pos: &[Range<usize>]) -> String {
+/// The part of a range within `len` positions
+fn bounded(range: &Range<usize>, len: usize) -> Range<usize> {
+    range.start..range.end.min(len)
+}
+
+fn extract_chars(line: &str, char_pos: &[Range<usize>], delim: &str) -> String {
     let chars: Vec<_> = line.chars().collect();
     char_pos
         .iter()
-        .cloned()
-        .flat_map(|range| range.filter_map(|i| chars.get(i)))
-        .collect()
+        .map(|range| {
+            bounded(range, chars.len())
+                .filter_map(|i| chars.get(i))
+                .collect::<String>()
+        })
+        .filter(|selected| !selected.is_empty())
+        .collect::<Vec<_>>()
+        .join(delim)
 }
 
-fn extract_bytes(line: &str, byte_pos: &[Range<usize>]) -> String {
+fn extract_bytes(line: &str, byte_pos: &[Range<usize>], delim: &str) -> String {
     let bytes = line.as_bytes();
-    let selected: Vec<_> = byte_pos
+    let selected = byte_pos
         .iter()
-        .cloned()
-        .flat_map(|range| range.filter_map(|i| bytes.get(i)).copied())
-        .collect();
+        .map(|range| {
+            bounded(range, bytes.len())
+                .filter_map(|i| bytes.get(i))
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .filter(|selected| !selected.is_empty())
+        .collect::<Vec<_>>()
+        .join(delim.as_bytes());
     String::from_utf8_lossy(&selected).to_string()
 }
 
-fn extract_fields(
-    record: &StringRecord,
-    field_pos: &[Range<usize>],
-) -> Vec<String> {
+fn extract_fields<'a>(
+    fields: &[&'a str],
+    field_pos: &'a [FieldItem],
+) -> Vec<&'a str> {
     field_pos
         .iter()
-        .cloned()
-        .flat_map(|range| range.filter_map(|i| record.get(i)))
-        .map(String::from)
+        .flat_map(|field| match field {
+            FieldItem::Range(range) => bounded(range, fields.len())
+                .filter_map(|i| fields.get(i))
+                .copied()
+                .collect(),
+            FieldItem::Literal(value) => vec![value.as_str()],
+        })
         .collect()
 }
 
+/// Turns a glob like `*_id` into an anchored regular expression
+fn glob_to_regex(glob: &str) -> Result<Regex, String> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            '[' => {
+                let class: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                let (negate, class) = match class.strip_prefix('!') {
+                    Some(class) => ("^", class),
+                    None => ("", class.as_str()),
+                };
+                pattern.push_str(&format!("[{}{}]", negate, class.replace('\\', "\\\\")));
+            }
+            _ => pattern.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).map_err(|_| format!("illegal field glob: \"{}\"", glob))
+}
+
+/// The positions of fields named in a header row
+fn header_pos(
+    names: &[String],
+    header: &[&str],
+    order: Order,
+    others: bool,
+) -> MyResult<FieldList> {
+    let index = |name: &str| header.iter().position(|field| *field == name);
+    let mut positions = vec![];
+    for name in names {
+        if let Some(value) = literal(name) {
+            positions.push(FieldItem::Literal(value.to_string()));
+        } else if let Some(i) = index(name) {
+            positions.push(FieldItem::Range(i..i + 1));
+        } else if name.contains(['*', '?', '[']) {
+            let re = glob_to_regex(name)?;
+            let len = positions.len();
+            positions.extend(
+                (0..header.len())
+                    .filter(|&i| re.is_match(header[i]))
+                    .map(|i| FieldItem::Range(i..i + 1)),
+            );
+            if positions.len() == len {
+                return Err(From::from(format!(
+                    "no field matches \"{}\"",
+                    name
+                )));
+            }
+        } else {
+            // A range of names, split at whichever dash joins two of them
+            let range = name.match_indices('-').find_map(|(at, _)| {
+                Some((index(&name[..at])?, index(&name[at + 1..])?))
+            });
+            match range {
+                Some((n1, n2)) if n1 > n2 => {
+                    return Err(From::from(format!(
+                        "First field in range \"{}\" \
+                        must come before second field \"{}\"",
+                        header[n1], header[n2]
+                    )))
+                }
+                Some((n1, n2)) => positions.push(FieldItem::Range(n1..n2 + 1)),
+                None => {
+                    return Err(From::from(format!(
+                        "unknown field \"{}\"",
+                        name
+                    )))
+                }
+            }
+        }
+    }
+    arrange_fields(positions, order, others)
+}
+
+/// The positions of the fields to cut, which for fields selected by name
+/// are found from the header row
+fn select_pos(extract: &Extract, header: &[&str]) -> MyResult<FieldList> {
+    match extract {
+        Headers {
+            names,
+            order,
+            complement,
+        } => header_pos(names, header, *order, *complement),
+        Fields(field_pos) => Ok(field_pos.clone()),
+        _ => Ok(vec![]),
+    }
+}
+
+fn cut_csv(
+    file: Box<dyn BufRead>,
+    extract: &Extract,
+    config: &Config,
+) -> MyResult<()> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(config.delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(file);
+    let mut wtr = WriterBuilder::new()
+        .delimiter(config.delimiter)
+        .flexible(true)
+        .from_writer(io::stdout());
+    let mut positions = None;
+    for result in reader.records() {
+        let record = result?;
+        let record: Vec<_> = record.iter().collect();
+        if positions.is_none() {
+            positions = Some(select_pos(extract, &record)?);
+        }
+        let field_pos = positions.as_deref().unwrap_or_default();
+        // A line without a delimiter is printed whole, as cut does, unless
+        // it is skipped
+        let fields = if record.len() == 1 {
+            if config.only_delimited {
+                continue;
+            }
+            record
+        } else {
+            extract_fields(&record, field_pos)
+        };
+        match &config.output_delimiter {
+            Some(delim) => println!("{}", fields.join(delim)),
+            None => wtr.write_record(&fields)?,
+        }
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn cut_split(
+    file: Box<dyn BufRead>,
+    split: &Split,
+    extract: &Extract,
+    config: &Config,
+) -> MyResult<()> {
+    let delim = match &config.output_delimiter {
+        Some(delim) => delim.clone(),
+        None => char::from(config.delimiter).to_string(),
+    };
+    let mut positions = None;
+    for line in file.lines() {
+        let line = line?;
+        let fields = split.fields(&line);
+        if positions.is_none() {
+            positions = Some(select_pos(extract, &fields)?);
+        }
+        let field_pos = positions.as_deref().unwrap_or_default();
+        if fields.len() < 2 {
+            if config.only_delimited {
+                continue;
+            }
+            // A single word still has whitespace split off around it
+            if !matches!(split, Split::Whitespace) || fields.is_empty() {
+                println!("{}", line);
+                continue;
+            }
+        }
+        println!("{}", extract_fields(&fields, field_pos).join(&delim));
+    }
+    Ok(())
+}
+
 pub fn run(config: Config) -> MyResult<()> {
+    let delim = config.output_delimiter.as_deref().unwrap_or("");
     for filename in &config.files {
         match open(filename) {
             Err(err) => eprintln!("{}: {}", filename, err),
             Ok(file) => match &config.extract {
-                Fields(field_pos) => {
-                    let mut reader = ReaderBuilder::new()
-                        .delimiter(config.delimiter)
-                        .has_headers(false)
-                        .from_reader(file);
-                    let mut wtr = WriterBuilder::new()
-                        .delimiter(config.delimiter)
-                        .from_writer(io::stdout());
-                    for result in reader.records() {
-                        let record = result?;
-                        let fields = extract_fields(&record, field_pos);
-                        wtr.write_record(&fields)?;
+                Fields(_) | Headers { .. } => match &config.split {
+                    Some(split) => {
+                        cut_split(file, split, &config.extract, &config)?
                     }
-                    wtr.flush()?;
-                }
+                    None => cut_csv(file, &config.extract, &config)?,
+                },
                 Bytes(byte_pos) => {
                     for line in file.lines() {
                         let line = line?;
-                        let bytes = extract_bytes(&line, byte_pos);
+                        let bytes = extract_bytes(&line, byte_pos, delim);
                         println!("{}", bytes);
                     }
                 }
                 Chars(char_pos) => {
                     for line in file.lines() {
                         let line = line?;
-                        let chars = extract_chars(&line, char_pos);
+                        let chars = extract_chars(&line, char_pos, delim);
                         println!("{}", chars);
                     }
                 }
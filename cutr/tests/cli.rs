@@ -337,3 +337,322 @@ fn tsv_c1_8() -> TestResult {
 fn repeated_value() -> TestResult {
     run(&[BOOKS, "-c", "1,1"], "tests/expected/books.c1,1.out")
 }
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_open_range() -> TestResult {
+    dies(&[BOOKS, "-f", "-"], "illegal list value: \"-\"")
+}
+
+// --------------------------------------------------
+#[test]
+fn open_end() -> TestResult {
+    run(&[BOOKS, "-f", "2-"], "tests/expected/books.f2-.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn open_start() -> TestResult {
+    run(&[BOOKS, "-f", "-2"], "tests/expected/books.f-2.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn merged_in_input_order() -> TestResult {
+    run(&[BOOKS, "-f", "3,1"], "tests/expected/books.f3,1.out")?;
+    run(&[BOOKS, "-f", "3,2-3,2"], "tests/expected/books.f2-.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn complement_fields() -> TestResult {
+    run(
+        &[BOOKS, "--complement", "-f", "2"],
+        "tests/expected/books.f2.complement.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn complement_bytes() -> TestResult {
+    run(
+        &[BOOKS, "--complement", "-b", "3-"],
+        "tests/expected/books.b3-.complement.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn complement_chars() -> TestResult {
+    run(
+        &[BOOKS, "--complement", "-c", "3-,1"],
+        "tests/expected/books.c1,3-.complement.out",
+    )
+}
+
+// --------------------------------------------------
+const UNDELIMITED: &str = "tests/inputs/undelimited.tsv";
+
+// --------------------------------------------------
+#[test]
+fn undelimited_lines_printed() -> TestResult {
+    run(
+        &[UNDELIMITED, "-f", "1,3"],
+        "tests/expected/undelimited.f1,3.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn only_delimited() -> TestResult {
+    run(
+        &[UNDELIMITED, "-s", "-f", "1,3"],
+        "tests/expected/undelimited.f1,3.s.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_only_delimited_chars() -> TestResult {
+    dies(
+        &[UNDELIMITED, "-s", "-c", "1"],
+        "--only-delimited only makes sense with --fields",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn output_delimiter_fields() -> TestResult {
+    run(
+        &[UNDELIMITED, "-f", "1,3", "--output-delimiter", " | "],
+        "tests/expected/undelimited.f1,3.odelim.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn output_delimiter_bytes() -> TestResult {
+    run(
+        &[UNDELIMITED, "-b", "1-2,4-", "--output-delimiter", "::"],
+        "tests/expected/undelimited.b1-2,4-.odelim.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn whitespace_fields() -> TestResult {
+    run(
+        &["tests/inputs/ps.txt", "-w", "-f", "1,4"],
+        "tests/expected/ps.txt.w.f1,4.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn whitespace_single_field() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-w", "-f", "1"])
+        .write_stdin("  x\n a  b\n\n")
+        .assert()
+        .success()
+        .stdout("x\na\n\n");
+    Command::cargo_bin(PRG)?
+        .args(["-w", "-s", "-f", "2"])
+        .write_stdin("x\n  x\n a  b\n\n")
+        .assert()
+        .success()
+        .stdout("b\n");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_fields() -> TestResult {
+    run(
+        &[
+            "tests/inputs/report.txt",
+            "--delim-regex",
+            " *\\| *",
+            "-f",
+            "3,1",
+            "--output-delimiter",
+            ",",
+            "-s",
+        ],
+        "tests/expected/report.txt.regex.f1,3.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_whitespace_bytes() -> TestResult {
+    dies(
+        &[CSV, "-w", "-b", "1"],
+        "--whitespace and --delim-regex only make sense with --fields",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_delim_regex() -> TestResult {
+    dies(&[CSV, "--delim-regex", "(", "-f", "1"], "unclosed group")
+}
+
+// --------------------------------------------------
+#[test]
+fn header_names() -> TestResult {
+    run(
+        &[CSV, "-d", ",", "-F", "director,title"],
+        "tests/expected/movies1.csv.Fdirector,title.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn header_range() -> TestResult {
+    run(
+        &[CSV, "-d", ",", "-F", "year-director"],
+        "tests/expected/movies1.csv.Fyear-director.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn header_glob_complement() -> TestResult {
+    run(
+        &[CSV, "-d", ",", "-F", "[!t]*", "--complement"],
+        "tests/expected/movies1.csv.Fglob.complement.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unknown_header() -> TestResult {
+    dies(&[CSV, "-d", ",", "-F", "title,rating"], "unknown field \"rating\"")
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unmatched_header_glob() -> TestResult {
+    dies(&[CSV, "-d", ",", "-F", "r*"], "no field matches \"r*\"")
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_decreasing_header_range() -> TestResult {
+    dies(
+        &[CSV, "-d", ",", "-F", "director-title"],
+        "First field in range \"director\" \
+        must come before second field \"title\"",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn plain_fields_verbatim() -> TestResult {
+    run(
+        &["tests/inputs/quotes.tsv", "-f", "2-3"],
+        "tests/expected/quotes.tsv.f2-3.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn plain_fields_split_quotes() -> TestResult {
+    run(
+        &["tests/inputs/movies2.csv", "-d", ",", "-f", "1,3"],
+        "tests/expected/movies2.csv.f1,3.out",
+    )?;
+    run(
+        &[
+            "tests/inputs/movies2.csv",
+            "-d",
+            ",",
+            "-f",
+            "1,3",
+            "--csv",
+            "--no-quote",
+        ],
+        "tests/expected/movies2.csv.f1,3.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn csv_fields_quoted() -> TestResult {
+    run(
+        &["tests/inputs/movies2.csv", "-d", ",", "-f", "1,3", "--csv"],
+        "tests/expected/movies2.csv.f1,3.csv.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn order_as_given() -> TestResult {
+    run(
+        &[BOOKS, "--order=as-given", "-f", "3,1,1"],
+        "tests/expected/books.f3,1,1.as-given.out",
+    )?;
+    run(&[BOOKS, "-f", "3,1,1"], "tests/expected/books.f3,1.out")
+}
+
+// --------------------------------------------------
+#[test]
+fn literal_field() -> TestResult {
+    run(
+        &[BOOKS, "--order=as-given", "-f", "2,' = ',1"],
+        "tests/expected/books.f2,literal,1.as-given.out",
+    )?;
+    // As typed in the shell, without the quotes being quoted too
+    run(
+        &[BOOKS, "--order=as-given", "-f", "2, = ,1"],
+        "tests/expected/books.f2,literal,1.as-given.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn literal_header_field() -> TestResult {
+    run(
+        &[CSV, "-d", ",", "--order=as-given", "-F", "director,'-',title"],
+        "tests/expected/movies1.csv.Fdirector,literal,title.as-given.out",
+    )?;
+    run(
+        &[CSV, "-d", ",", "--order=as-given", "-F", "director, | ,title"],
+        "tests/expected/movies1.csv.Fdirector,bar,title.as-given.out",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_literal_in_input_order() -> TestResult {
+    dies(
+        &[BOOKS, "-f", "1,'='"],
+        "literal \"=\" needs --order=as-given without --complement",
+    )?;
+    dies(
+        &[BOOKS, "-f", "1,="],
+        "literal \"=\" needs --order=as-given without --complement",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_literal() -> TestResult {
+    dies(
+        &[BOOKS, "--order=as-given", "-f", "1,,2"],
+        "illegal list value: \"\"",
+    )?;
+    dies(
+        &[BOOKS, "--order=as-given", "-f", "1,x,2"],
+        "illegal list value: \"x\"",
+    )?;
+    dies(
+        &[CSV, "-d", ",", "--order=as-given", "-F", "title,,year"],
+        "illegal list value: \"\"",
+    )?;
+    dies(
+        &[CSV, "-d", ",", "--order=as-given", "-F", "title,x,year"],
+        "unknown field \"x\"",
+    )
+}
@@ -39,3 +39,7 @@ find "$IN_DIR/a" "$IN_DIR/d" -name \*.txt > "$OUT_DIR/name_txt_path_a_d.txt"
 find "$IN_DIR" -name a* > "$OUT_DIR/name_a.txt"
 find "$IN_DIR" -type f -name a* > "$OUT_DIR/type_f_name_a.txt"
 find "$IN_DIR" -type d -name a* > "$OUT_DIR/type_d_name_a.txt"
+
+find "$IN_DIR/a" ! -type d > "$OUT_DIR/expr_not_type_d_path_a.txt"
+find "$IN_DIR/a" -type d -o -size -1 > "$OUT_DIR/expr_type_d_or_size_path_a.txt"
+find "$IN_DIR/a" \( -type f -o -type l \) -a -not -empty > "$OUT_DIR/expr_parens_path_a.txt"
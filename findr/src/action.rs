@@ -0,0 +1,232 @@
+use crate::{printf::Format, walk::Entry};
+use std::{
+    env,
+    ffi::OsString,
+    fs,
+    io::{self, BufWriter, Stdout, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+/// Bytes of arguments allowed per batched command, as `xargs` defaults to
+const ARG_MAX: usize = 128 * 1024;
+
+/// Something done to an entry, which also counts as a test
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Print the path followed by a newline
+    Print,
+    /// Print the path followed by a NUL
+    Print0,
+    /// Print details of the entry in a format
+    Printf(Format),
+    /// Run a command for each entry, replacing `{}` with its path; true
+    /// if the command succeeds
+    Exec(Vec<String>),
+    /// Run a command for as many entries at once as fit, appending their
+    /// paths after the other arguments; always true
+    ExecBatch { command: Vec<String>, id: usize },
+    /// Remove the entry, refusing to remove a starting path
+    Delete,
+    /// Do not descend into the entry if it is a directory; always true
+    Prune,
+}
+
+/// Paths waiting for a batched command
+#[derive(Debug)]
+struct Batch {
+    command: Vec<String>,
+    paths: Vec<PathBuf>,
+    size: usize,
+}
+
+/// State shared by the actions over a whole walk
+pub struct Context {
+    /// Flushed after each entry is printed, so matches appear as they are
+    /// found
+    out: BufWriter<Stdout>,
+    batches: Vec<Option<Batch>>,
+    /// Bytes of arguments each batched command may be given
+    arg_limit: usize,
+    /// Whether the current entry was pruned
+    pruned: bool,
+    failed: bool,
+    error: Option<io::Error>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Context {
+    pub fn new() -> Self {
+        let env_size: usize = env::vars_os()
+            .map(|(key, value)| key.len() + value.len() + 2)
+            .sum();
+        Context {
+            out: BufWriter::new(io::stdout()),
+            batches: vec![],
+            arg_limit: ARG_MAX.saturating_sub(env_size),
+            pruned: false,
+            failed: false,
+            error: None,
+        }
+    }
+
+    /// The first error writing the output, if any
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+
+    /// Makes the walk exit with an error once it is finished
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    /// Whether the entry just evaluated was pruned
+    pub fn take_pruned(&mut self) -> bool {
+        std::mem::take(&mut self.pruned)
+    }
+
+    pub fn act(&mut self, action: &Action, entry: &Entry) -> bool {
+        let path = entry.path();
+        match action {
+            Action::Print => self.print(path, b'\n'),
+            Action::Print0 => self.print(path, b'\0'),
+            Action::Printf(format) => {
+                let result = format
+                    .write(entry, &mut self.out)
+                    .and_then(|_| self.out.flush());
+                if let Err(e) = result {
+                    self.error.get_or_insert(e);
+                }
+                true
+            }
+            Action::Exec(command) => {
+                let args = command.iter().map(|arg| substitute(arg, path));
+                self.exec(args.collect())
+            }
+            Action::ExecBatch { command, id } => {
+                self.add_to_batch(command, *id, path);
+                true
+            }
+            Action::Delete => self.delete(entry),
+            Action::Prune => {
+                self.pruned = true;
+                true
+            }
+        }
+    }
+
+    /// Runs the batched commands still waiting and flushes the output
+    pub fn finish(mut self) -> io::Result<ExitCode> {
+        for batch in std::mem::take(&mut self.batches).into_iter().flatten() {
+            self.run_batch(batch);
+        }
+        self.out.flush()?;
+        match self.error {
+            Some(e) => Err(e),
+            None if self.failed => Ok(ExitCode::FAILURE),
+            None => Ok(ExitCode::SUCCESS),
+        }
+    }
+
+    fn print(&mut self, path: &Path, terminator: u8) -> bool {
+        let result = self
+            .out
+            .write_all(path.as_os_str().as_encoded_bytes())
+            .and_then(|_| self.out.write_all(&[terminator]))
+            .and_then(|_| self.out.flush());
+        if let Err(e) = result {
+            self.error.get_or_insert(e);
+        }
+        true
+    }
+
+    /// Runs a command, keeping its output after what was printed before
+    fn exec(&mut self, args: Vec<OsString>) -> bool {
+        if let Err(e) = self.out.flush() {
+            self.error.get_or_insert(e);
+        }
+        match Command::new(&args[0]).args(&args[1..]).status() {
+            Ok(status) => status.success(),
+            Err(e) => {
+                eprintln!("{}: {}", args[0].to_string_lossy(), e);
+                self.failed = true;
+                false
+            }
+        }
+    }
+
+    fn add_to_batch(&mut self, command: &[String], id: usize, path: &Path) {
+        if self.batches.len() <= id {
+            self.batches.resize_with(id + 1, || None);
+        }
+        let size = path.as_os_str().len() + 1;
+        let full = self.batches[id].as_ref().is_some_and(|batch| {
+            !batch.paths.is_empty() && batch.size + size > self.arg_limit
+        });
+        if full {
+            if let Some(batch) = self.batches[id].take() {
+                self.run_batch(batch);
+            }
+        }
+        let batch = self.batches[id].get_or_insert_with(|| Batch {
+            command: command.to_vec(),
+            paths: vec![],
+            size: command.iter().map(|arg| arg.len() + 1).sum(),
+        });
+        batch.paths.push(path.to_path_buf());
+        batch.size += size;
+    }
+
+    fn run_batch(&mut self, batch: Batch) {
+        if batch.paths.is_empty() {
+            return;
+        }
+        let args = batch
+            .command
+            .iter()
+            .map(OsString::from)
+            .chain(batch.paths.into_iter().map(OsString::from));
+        if !self.exec(args.collect()) {
+            self.failed = true;
+        }
+    }
+
+    fn delete(&mut self, entry: &Entry) -> bool {
+        let path = entry.path();
+        if entry.depth() == 0 {
+            eprintln!("{}: refusing to delete a starting path", path.display());
+            self.failed = true;
+            return false;
+        }
+        let result = if entry.file_type().is_dir() {
+            fs::remove_dir(path)
+        } else {
+            fs::remove_file(path)
+        };
+        match result {
+            Ok(()) => true,
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                self.failed = true;
+                false
+            }
+        }
+    }
+}
+
+/// Replaces each `{}` in a command argument with the path
+fn substitute(arg: &str, path: &Path) -> OsString {
+    let mut result = OsString::new();
+    for (i, part) in arg.split("{}").enumerate() {
+        if i > 0 {
+            result.push(path);
+        }
+        result.push(part);
+    }
+    result
+}
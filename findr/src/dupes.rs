@@ -0,0 +1,200 @@
+use crate::walk::Entry;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    hash::Hash,
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+/// Bytes read from the start of each file for its partial hash
+const PARTIAL_SIZE: u64 = 4096;
+
+/// What happens to the files duplicating the first of their group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolve {
+    /// Only print the groups
+    List,
+    /// Replace them with hard links to the first
+    Hardlink,
+    /// Remove them
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DupeOptions {
+    pub resolve: Resolve,
+    /// Print what would be done instead of doing it
+    pub dry_run: bool,
+}
+
+/// Regular files collected to be compared once the walk is finished
+pub struct Duplicates {
+    options: DupeOptions,
+    /// Paths by size, each size in the order it was first found
+    by_size: HashMap<u64, usize>,
+    sizes: Vec<(u64, Vec<PathBuf>)>,
+    /// The device and inode of each file, to skip other links to it
+    seen: HashSet<(u64, u64)>,
+}
+
+impl Duplicates {
+    pub fn new(options: DupeOptions) -> Self {
+        Duplicates {
+            options,
+            by_size: HashMap::new(),
+            sizes: vec![],
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &Entry) {
+        let Some(meta) = entry.metadata() else {
+            return;
+        };
+        if let Some(id) = file_id(meta) {
+            if !self.seen.insert(id) {
+                return;
+            }
+        }
+        let size = meta.len();
+        let i = *self.by_size.entry(size).or_insert_with(|| {
+            self.sizes.push((size, vec![]));
+            self.sizes.len() - 1
+        });
+        self.sizes[i].1.push(entry.path().to_path_buf());
+    }
+
+    /// Prints the groups of identical files, first as found, and resolves
+    /// the rest, returning whether every file could be read and changed
+    pub fn finish(self) -> io::Result<bool> {
+        let mut ok = true;
+        let mut groups = vec![];
+        for (size, paths) in self.sizes {
+            if paths.len() < 2 {
+                continue;
+            }
+            let partial = group_by(paths, &mut ok, |path| {
+                hash(path, Some(PARTIAL_SIZE))
+            });
+            if size <= PARTIAL_SIZE {
+                groups.extend(partial);
+                continue;
+            }
+            for paths in partial {
+                groups.extend(group_by(paths, &mut ok, |path| {
+                    hash(path, None)
+                }));
+            }
+        }
+
+        let mut out = BufWriter::new(io::stdout());
+        for (i, group) in groups.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            let (first, rest) = group.split_first().expect("a group of two");
+            writeln!(out, "{}", first.display())?;
+            for path in rest {
+                ok &= self.options.resolve(first, path, &mut out)?;
+            }
+        }
+        out.flush()?;
+        Ok(ok)
+    }
+}
+
+impl DupeOptions {
+    /// Prints a duplicate of `first` with what is done to it, returning
+    /// whether that succeeded
+    fn resolve(
+        &self,
+        first: &Path,
+        path: &Path,
+        out: &mut impl Write,
+    ) -> io::Result<bool> {
+        let (done, result) = match self.resolve {
+            Resolve::List => {
+                writeln!(out, "{}", path.display())?;
+                return Ok(true);
+            }
+            Resolve::Hardlink if self.dry_run => ("would link", Ok(())),
+            Resolve::Hardlink => ("linked", hard_link(first, path)),
+            Resolve::Delete if self.dry_run => ("would delete", Ok(())),
+            Resolve::Delete => ("deleted", fs::remove_file(path)),
+        };
+        match result {
+            Ok(()) => {
+                writeln!(out, "{} ({})", path.display(), done)?;
+                Ok(true)
+            }
+            Err(e) => {
+                out.flush()?;
+                eprintln!("{}: {}", path.display(), e);
+                Ok(false)
+            }
+        }
+    }
+}
+
+/// Splits paths into groups with the same key, in the order each key was
+/// first found, dropping any path alone in its group
+fn group_by<K: Hash + Eq>(
+    paths: Vec<PathBuf>,
+    ok: &mut bool,
+    mut key: impl FnMut(&Path) -> io::Result<K>,
+) -> Vec<Vec<PathBuf>> {
+    let mut index = HashMap::new();
+    let mut groups: Vec<Vec<PathBuf>> = vec![];
+    for path in paths {
+        match key(&path) {
+            Ok(key) => {
+                let i = *index.entry(key).or_insert_with(|| {
+                    groups.push(vec![]);
+                    groups.len() - 1
+                });
+                groups[i].push(path);
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                *ok = false;
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// The hash of a file's contents, or of at most `limit` bytes of them
+fn hash(path: &Path, limit: Option<u64>) -> io::Result<blake3::Hash> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    match limit {
+        Some(limit) => io::copy(&mut file.take(limit), &mut hasher)?,
+        None => io::copy(&mut file, &mut hasher)?,
+    };
+    Ok(hasher.finalize())
+}
+
+/// Replaces `path` with a hard link to `first`, by renaming a new link
+/// over it so it is never missing
+fn hard_link(first: &Path, path: &Path) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".findr-link");
+    let temp = PathBuf::from(temp);
+    fs::hard_link(first, &temp)?;
+    fs::rename(&temp, path).inspect_err(|_| {
+        let _ = fs::remove_file(&temp);
+    })
+}
+
+#[cfg(unix)]
+fn file_id(meta: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((meta.dev(), meta.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_meta: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
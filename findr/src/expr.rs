@@ -0,0 +1,748 @@
+use crate::{
+    action::{Action, Context},
+    perm::{self, Perm},
+    walk::{Entry, WalkOptions},
+    MyResult,
+};
+use clap::ValueEnum;
+use regex::{Regex, RegexBuilder};
+use std::{
+    fs::{self, Metadata},
+    io,
+    time::SystemTime,
+};
+use EntryType::*;
+
+const DAY: u64 = 24 * 60 * 60;
+const MINUTE: u64 = 60;
+
+/// Operators and tests that can start an expression on the command line
+const PRIMARIES: &[&str] = &[
+    "(", "!", "-not", "-true", "-false", "-name", "-type", "-size", "-mtime",
+    "-atime", "-ctime", "-mmin", "-newer", "-empty", "-print", "-print0",
+    "-exec", "-delete", "-prune", "-maxdepth", "-mindepth", "-follow",
+    "-iname", "-path", "-ipath", "-regex", "-iregex", "-printf", "-user",
+    "-group", "-uid", "-gid", "-nouser", "-nogroup", "-perm",
+];
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum EntryType {
+    Dir,
+    File,
+    Link,
+}
+
+impl EntryType {
+    fn matches(&self, entry: &Entry) -> bool {
+        match self {
+            Dir => entry.file_type().is_dir(),
+            File => entry.file_type().is_file(),
+            Link => entry.file_type().is_symlink(),
+        }
+    }
+}
+
+impl std::str::FromStr for EntryType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "d" => Ok(EntryType::Dir),
+            "f" => Ok(EntryType::File),
+            "l" => Ok(EntryType::Link),
+            _ => Err("invalid entry type"),
+        }
+    }
+}
+
+impl ValueEnum for EntryType {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[Dir, File, Link]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        let value = match self {
+            EntryType::Dir => "d",
+            EntryType::File => "f",
+            EntryType::Link => "l",
+        };
+        Some(clap::builder::PossibleValue::new(value))
+    }
+}
+
+/// A numeric argument: `+N` for more than N, `-N` for less than N, or
+/// `N` for exactly N
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Less(i64),
+    Exactly(i64),
+    More(i64),
+}
+
+impl Bound {
+    fn matches(&self, value: i64) -> bool {
+        match *self {
+            Bound::Less(n) => value < n,
+            Bound::Exactly(n) => value == n,
+            Bound::More(n) => value > n,
+        }
+    }
+}
+
+impl std::str::FromStr for Bound {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str| {
+            n.parse::<i64>()
+                .ok()
+                .filter(|_| n.starts_with(|c: char| c.is_ascii_digit()))
+                .ok_or_else(|| format!("invalid number \"{}\"", s))
+        };
+        if let Some(n) = s.strip_prefix('+') {
+            parse(n).map(Bound::More)
+        } else if let Some(n) = s.strip_prefix('-') {
+            parse(n).map(Bound::Less)
+        } else {
+            parse(s).map(Bound::Exactly)
+        }
+    }
+}
+
+/// A `-size` argument: a bound on the size in units, rounded up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    bound: Bound,
+    unit: u64,
+}
+
+impl Size {
+    fn matches(&self, len: u64) -> bool {
+        let units = len.div_ceil(self.unit);
+        self.bound.matches(i64::try_from(units).unwrap_or(i64::MAX))
+    }
+}
+
+impl std::str::FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (n, unit) = match s.char_indices().last() {
+            Some((i, c)) if c.is_ascii_alphabetic() => {
+                let unit = match c {
+                    'c' => 1,
+                    'w' => 2,
+                    'b' => 512,
+                    'k' => 1 << 10,
+                    'M' => 1 << 20,
+                    'G' => 1 << 30,
+                    _ => return Err(format!("invalid size unit \"{}\"", c)),
+                };
+                (&s[..i], unit)
+            }
+            _ => (s, 512),
+        };
+        Ok(Size {
+            bound: n.parse().map_err(|_| format!("invalid size \"{}\"", s))?,
+            unit,
+        })
+    }
+}
+
+/// Which timestamp of an entry a test looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Time {
+    Accessed,
+    Changed,
+    Modified,
+}
+
+impl Time {
+    fn of(&self, meta: &Metadata) -> io::Result<SystemTime> {
+        match self {
+            Time::Accessed => meta.accessed(),
+            Time::Changed => changed(meta),
+            Time::Modified => meta.modified(),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn changed(meta: &Metadata) -> io::Result<SystemTime> {
+    use std::{os::unix::fs::MetadataExt, time::Duration};
+    let since_epoch =
+        Duration::new(meta.ctime() as u64, meta.ctime_nsec() as u32);
+    Ok(SystemTime::UNIX_EPOCH + since_epoch)
+}
+
+#[cfg(not(unix))]
+fn changed(meta: &Metadata) -> io::Result<SystemTime> {
+    meta.modified()
+}
+
+/// A single test on an entry
+#[derive(Debug, Clone)]
+pub enum Test {
+    True,
+    False,
+    /// The file name matches a regular expression
+    Name(Regex),
+    /// The whole path matches a regular expression
+    Path(Regex),
+    /// The entry is any of the types
+    Type(Vec<EntryType>),
+    Size(Size),
+    /// The time is `bound` whole units of `unit` seconds ago
+    Age {
+        time: Time,
+        unit: u64,
+        bound: Bound,
+    },
+    /// Modified more recently than the given time
+    Newer(SystemTime),
+    /// An empty regular file or directory
+    Empty,
+    /// Owned by the user ID
+    User(u32),
+    /// Owned by the group ID
+    Group(u32),
+    Uid(Bound),
+    Gid(Bound),
+    /// Owned by a user ID with no user
+    NoUser,
+    /// Owned by a group ID with no group
+    NoGroup,
+    Perm(Perm),
+}
+
+impl Test {
+    pub fn mtime(bound: Bound) -> Self {
+        Test::Age { time: Time::Modified, unit: DAY, bound }
+    }
+
+    pub fn atime(bound: Bound) -> Self {
+        Test::Age { time: Time::Accessed, unit: DAY, bound }
+    }
+
+    pub fn ctime(bound: Bound) -> Self {
+        Test::Age { time: Time::Changed, unit: DAY, bound }
+    }
+
+    pub fn mmin(bound: Bound) -> Self {
+        Test::Age { time: Time::Modified, unit: MINUTE, bound }
+    }
+
+    /// Newer than the modification time of `file`
+    pub fn newer(file: &str) -> MyResult<Self> {
+        fs::metadata(file)
+            .and_then(|meta| meta.modified())
+            .map(Test::Newer)
+            .map_err(|e| From::from(format!("{}: {}", file, e)))
+    }
+
+    fn matches(&self, candidate: &Candidate) -> bool {
+        match self {
+            Test::True => true,
+            Test::False => false,
+            Test::Name(re) => {
+                re.is_match(&candidate.entry.file_name().to_string_lossy())
+            }
+            Test::Path(re) => {
+                re.is_match(&candidate.entry.path().to_string_lossy())
+            }
+            Test::Type(types) => {
+                types.iter().any(|t| t.matches(candidate.entry))
+            }
+            Test::Size(size) => candidate
+                .metadata()
+                .is_some_and(|meta| size.matches(meta.len())),
+            Test::Age { time, unit, bound } => candidate
+                .metadata()
+                .and_then(|meta| time.of(meta).ok())
+                .is_some_and(|time| {
+                    bound.matches(age(candidate.now, time, *unit))
+                }),
+            Test::Newer(newer) => candidate
+                .metadata()
+                .and_then(|meta| meta.modified().ok())
+                .is_some_and(|modified| modified > *newer),
+            Test::Empty => candidate
+                .metadata()
+                .is_some_and(|meta| is_empty(candidate.entry, meta)),
+            Test::User(id) => {
+                owner(candidate).is_some_and(|(uid, _)| uid == *id)
+            }
+            Test::Group(id) => {
+                owner(candidate).is_some_and(|(_, gid)| gid == *id)
+            }
+            Test::Uid(bound) => owner(candidate)
+                .is_some_and(|(uid, _)| bound.matches(uid.into())),
+            Test::Gid(bound) => owner(candidate)
+                .is_some_and(|(_, gid)| bound.matches(gid.into())),
+            Test::NoUser => {
+                owner(candidate).is_some_and(|(uid, _)| !user_exists(uid))
+            }
+            Test::NoGroup => {
+                owner(candidate).is_some_and(|(_, gid)| !group_exists(gid))
+            }
+            Test::Perm(perm) => candidate
+                .metadata()
+                .is_some_and(|meta| perm.matches(perm::mode(meta))),
+        }
+    }
+}
+
+/// The user and group IDs owning an entry
+#[cfg(unix)]
+fn owner(candidate: &Candidate) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    candidate.metadata().map(|meta| (meta.uid(), meta.gid()))
+}
+
+#[cfg(not(unix))]
+fn owner(_candidate: &Candidate) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(unix)]
+fn user_exists(uid: u32) -> bool {
+    uzers::get_user_by_uid(uid).is_some()
+}
+
+#[cfg(not(unix))]
+fn user_exists(_uid: u32) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn group_exists(gid: u32) -> bool {
+    uzers::get_group_by_gid(gid).is_some()
+}
+
+#[cfg(not(unix))]
+fn group_exists(_gid: u32) -> bool {
+    true
+}
+
+/// The ID of a user given by name or number
+fn user_id(user: &str) -> MyResult<u32> {
+    #[cfg(unix)]
+    if let Some(found) = uzers::get_user_by_name(user) {
+        return Ok(found.uid());
+    }
+    user.parse()
+        .map_err(|_| From::from(format!("unknown user \"{}\"", user)))
+}
+
+/// The ID of a group given by name or number
+fn group_id(group: &str) -> MyResult<u32> {
+    #[cfg(unix)]
+    if let Some(found) = uzers::get_group_by_name(group) {
+        return Ok(found.gid());
+    }
+    group
+        .parse()
+        .map_err(|_| From::from(format!("unknown group \"{}\"", group)))
+}
+
+/// Translates a shell pattern, where `*` and `?` match any characters
+/// including `/` and `[...]` or `[!...]` a set of them, into a regular
+/// expression matching all of a string
+fn glob(pattern: &str, ignore_case: bool) -> Result<Regex, regex::Error> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut re = String::from("^");
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '*' => re.push_str(".*"),
+            '?' => re.push('.'),
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                push_escaped(&mut re, chars[i]);
+            }
+            '[' => match class(&chars, i + 1) {
+                Some((set, end)) => {
+                    re.push_str(&set);
+                    i = end;
+                }
+                None => push_escaped(&mut re, '['),
+            },
+            c => push_escaped(&mut re, c),
+        }
+        i += 1;
+    }
+    re.push('$');
+    RegexBuilder::new(&re)
+        .case_insensitive(ignore_case)
+        .dot_matches_new_line(true)
+        .build()
+}
+
+/// Translates a `[...]` set starting after its `[` into a regular
+/// expression class, with the index of its closing `]`, or `None` if it
+/// is not closed
+fn class(chars: &[char], start: usize) -> Option<(String, usize)> {
+    let mut set = String::from("[");
+    let mut i = start;
+    if matches!(chars.get(i), Some('!' | '^')) {
+        set.push('^');
+        i += 1;
+    }
+    // A `]` first in the set is one of its characters
+    let first = i;
+    loop {
+        match *chars.get(i)? {
+            ']' if i > first => break,
+            '[' if chars.get(i + 1) == Some(&':') => {
+                // A class like [:alpha:], which regex supports as is
+                let end = (i + 2..chars.len())
+                    .find(|&j| chars[j] == ']' && chars[j - 1] == ':')?;
+                set.extend(&chars[i..=end]);
+                i = end;
+            }
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                push_escaped(&mut set, chars[i]);
+            }
+            c => push_escaped(&mut set, c),
+        }
+        i += 1;
+    }
+    set.push(']');
+    Some((set, i))
+}
+
+/// Pushes a character that is literal inside or outside a class
+fn push_escaped(re: &mut String, c: char) {
+    if c.is_ascii_punctuation() && c != '-' {
+        re.push('\\');
+    }
+    re.push(c);
+}
+
+/// Whole units of `unit` seconds between `time` and `now`, rounded down
+fn age(now: SystemTime, time: SystemTime, unit: u64) -> i64 {
+    let secs = match now.duration_since(time) {
+        Ok(elapsed) => elapsed.as_secs_f64(),
+        Err(e) => -e.duration().as_secs_f64(),
+    };
+    (secs / unit as f64).floor() as i64
+}
+
+fn is_empty(entry: &Entry, meta: &Metadata) -> bool {
+    if meta.is_dir() {
+        fs::read_dir(entry.path()).is_ok_and(|mut dir| dir.next().is_none())
+    } else {
+        meta.is_file() && meta.len() == 0
+    }
+}
+
+/// A find expression
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Test(Test),
+    Action(Action),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl From<Test> for Expr {
+    fn from(test: Test) -> Self {
+        Expr::Test(test)
+    }
+}
+
+impl Expr {
+    /// True only if every expression is
+    pub fn all(exprs: impl IntoIterator<Item = Expr>) -> Self {
+        exprs
+            .into_iter()
+            .reduce(|a, b| Expr::And(Box::new(a), Box::new(b)))
+            .unwrap_or(Expr::Test(Test::True))
+    }
+
+    /// True if any of the expressions is
+    pub fn any(exprs: impl IntoIterator<Item = Expr>) -> Self {
+        exprs
+            .into_iter()
+            .reduce(|a, b| Expr::Or(Box::new(a), Box::new(b)))
+            .unwrap_or(Expr::Test(Test::False))
+    }
+
+    /// Whether any part of the expression is an action other than
+    /// `-prune`, so that matches are not printed by default
+    pub fn has_action(&self) -> bool {
+        self.any_action(&|action| !matches!(action, Action::Prune))
+    }
+
+    /// Whether the expression can delete entries, which must then be
+    /// visited before their directories
+    pub fn deletes(&self) -> bool {
+        self.any_action(&|action| matches!(action, Action::Delete))
+    }
+
+    fn any_action(&self, pred: &impl Fn(&Action) -> bool) -> bool {
+        match self {
+            Expr::Test(_) => false,
+            Expr::Action(action) => pred(action),
+            Expr::Not(expr) => expr.any_action(pred),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.any_action(pred) || b.any_action(pred)
+            }
+        }
+    }
+
+    /// Evaluates the expression, performing the actions reached
+    pub fn eval(&self, candidate: &Candidate, ctx: &mut Context) -> bool {
+        match self {
+            Expr::Test(test) => test.matches(candidate),
+            Expr::Action(action) => ctx.act(action, candidate.entry),
+            Expr::Not(expr) => !expr.eval(candidate, ctx),
+            Expr::And(a, b) => {
+                a.eval(candidate, ctx) && b.eval(candidate, ctx)
+            }
+            Expr::Or(a, b) => a.eval(candidate, ctx) || b.eval(candidate, ctx),
+        }
+    }
+}
+
+/// An entry under test
+pub struct Candidate<'a> {
+    entry: &'a Entry,
+    now: SystemTime,
+}
+
+impl<'a> Candidate<'a> {
+    pub fn new(entry: &'a Entry, now: SystemTime) -> Self {
+        Candidate { entry, now }
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        self.entry.metadata()
+    }
+}
+
+/// Whether a command-line argument starts the expression
+pub fn starts_expression(arg: &str) -> bool {
+    PRIMARIES.contains(&arg)
+}
+
+/// Parses an expression from command-line arguments, with `!`/`-not`
+/// binding tightest, then `-a`/`-and` (or no operator), then `-o`/`-or`
+pub fn parse(args: &[String]) -> MyResult<(Expr, WalkOptions)> {
+    let mut parser = ExprParser {
+        args,
+        pos: 0,
+        batches: 0,
+        walk: WalkOptions::default(),
+    };
+    if args.is_empty() {
+        return Ok((Expr::Test(Test::True), parser.walk));
+    }
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok((expr, parser.walk)),
+        Some(")") => Err(From::from("unmatched \")\"")),
+        Some(arg) => Err(From::from(format!("unexpected \"{}\"", arg))),
+    }
+}
+
+fn invalid(option: &str, value: &str) -> String {
+    format!("Invalid {} \"{}\"", option, value)
+}
+
+struct ExprParser<'a> {
+    args: &'a [String],
+    pos: usize,
+    /// Number of `-exec ... {} +` actions so far
+    batches: usize,
+    walk: WalkOptions,
+}
+
+impl<'a> ExprParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.args.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.pos)?;
+        self.pos += 1;
+        Some(arg)
+    }
+
+    /// The argument of the option just read
+    fn value(&mut self, option: &str) -> MyResult<&'a str> {
+        self.next().ok_or_else(|| {
+            From::from(format!("missing argument to \"{}\"", option))
+        })
+    }
+
+    /// The argument of a depth option
+    fn depth(&mut self, option: &str) -> MyResult<usize> {
+        let value = self.value(option)?;
+        value.parse().map_err(|_| {
+            From::from(format!(
+                "invalid argument \"{}\" to \"{}\"",
+                value, option
+            ))
+        })
+    }
+
+    /// The command of an `-exec`, up to `;` or `{} +`
+    fn exec(&mut self) -> MyResult<Action> {
+        let start = self.pos;
+        loop {
+            match self.next() {
+                Some(";") => {
+                    let command = &self.args[start..self.pos - 1];
+                    if command.is_empty() {
+                        break;
+                    }
+                    return Ok(Action::Exec(command.to_vec()));
+                }
+                Some("+")
+                    if self.pos - start > 2
+                        && self.args[self.pos - 2] == "{}" =>
+                {
+                    let command = self.args[start..self.pos - 2].to_vec();
+                    self.batches += 1;
+                    return Ok(Action::ExecBatch {
+                        command,
+                        id: self.batches - 1,
+                    });
+                }
+                Some(_) => {}
+                None => break,
+            }
+        }
+        Err(From::from("missing argument to \"-exec\""))
+    }
+
+    fn or(&mut self) -> MyResult<Expr> {
+        let mut expr = self.and()?;
+        while matches!(self.peek(), Some("-o" | "-or")) {
+            self.pos += 1;
+            let rhs = self.and()?;
+            expr = Expr::Or(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> MyResult<Expr> {
+        let mut expr = self.not()?;
+        loop {
+            match self.peek() {
+                Some("-a" | "-and") => self.pos += 1,
+                None | Some(")" | "-o" | "-or") => break,
+                Some(_) => {}
+            }
+            let rhs = self.not()?;
+            expr = Expr::And(Box::new(expr), Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> MyResult<Expr> {
+        if matches!(self.peek(), Some("!" | "-not")) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> MyResult<Expr> {
+        let Some(arg) = self.next() else {
+            return Err(From::from("expected an expression at the end"));
+        };
+        let test = match arg {
+            "(" => {
+                let expr = self.or()?;
+                return match self.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err(From::from("missing \")\"")),
+                };
+            }
+            "-true" => Test::True,
+            "-false" => Test::False,
+            "-name" | "-iname" => {
+                let name = self.value(arg)?;
+                Test::Name(
+                    glob(name, arg == "-iname")
+                        .map_err(|_| invalid(arg, name))?,
+                )
+            }
+            "-path" | "-ipath" => {
+                let path = self.value(arg)?;
+                Test::Path(
+                    glob(path, arg == "-ipath")
+                        .map_err(|_| invalid(arg, path))?,
+                )
+            }
+            "-regex" | "-iregex" => {
+                let re = self.value(arg)?;
+                Test::Path(
+                    RegexBuilder::new(&format!("^(?:{})$", re))
+                        .case_insensitive(arg == "-iregex")
+                        .build()
+                        .map_err(|_| invalid(arg, re))?,
+                )
+            }
+            "-type" => {
+                let types = self.value(arg)?;
+                Test::Type(
+                    types
+                        .split(',')
+                        .map(|t| {
+                            t.parse().map_err(|_| {
+                                format!("Invalid -type \"{}\"", types)
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "-size" => Test::Size(self.value(arg)?.parse()?),
+            "-mtime" => Test::mtime(self.value(arg)?.parse()?),
+            "-atime" => Test::atime(self.value(arg)?.parse()?),
+            "-ctime" => Test::ctime(self.value(arg)?.parse()?),
+            "-mmin" => Test::mmin(self.value(arg)?.parse()?),
+            "-newer" => Test::newer(self.value(arg)?)?,
+            "-empty" => Test::Empty,
+            "-user" => Test::User(user_id(self.value(arg)?)?),
+            "-group" => Test::Group(group_id(self.value(arg)?)?),
+            "-uid" => Test::Uid(self.value(arg)?.parse()?),
+            "-gid" => Test::Gid(self.value(arg)?.parse()?),
+            "-nouser" => Test::NoUser,
+            "-nogroup" => Test::NoGroup,
+            "-perm" => Test::Perm(self.value(arg)?.parse()?),
+            "-print" => return Ok(Expr::Action(Action::Print)),
+            "-print0" => return Ok(Expr::Action(Action::Print0)),
+            "-printf" => {
+                let format = self.value(arg)?.parse()?;
+                return Ok(Expr::Action(Action::Printf(format)));
+            }
+            "-delete" => return Ok(Expr::Action(Action::Delete)),
+            "-exec" => return Ok(Expr::Action(self.exec()?)),
+            "-prune" => return Ok(Expr::Action(Action::Prune)),
+            "-maxdepth" => {
+                self.walk.max_depth = Some(self.depth(arg)?);
+                Test::True
+            }
+            "-mindepth" => {
+                self.walk.min_depth = self.depth(arg)?;
+                Test::True
+            }
+            "-follow" => {
+                self.walk.follow = true;
+                Test::True
+            }
+            ")" => {
+                return Err(From::from("expected an expression before \")\""))
+            }
+            _ => {
+                return Err(From::from(format!("unknown predicate \"{}\"", arg)))
+            }
+        };
+        Ok(Expr::Test(test))
+    }
+}
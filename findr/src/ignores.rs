@@ -0,0 +1,133 @@
+use crate::walk::Entry;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Ignore files read from each directory, lowest precedence first
+const IGNORE_FILES: &[&str] = &[".git/info/exclude", ".gitignore", ".ignore"];
+
+/// Decides which entries under one starting path are skipped for being
+/// hidden or matched by ignore files
+pub struct Ignores {
+    /// Whether ignore files are read, rather than only hidden entries
+    /// being skipped
+    respect_ignore: bool,
+    hidden: bool,
+    global: Gitignore,
+    /// The starting path as given and made absolute
+    start: PathBuf,
+    abs_start: PathBuf,
+    /// The highest directory whose ignore files apply: the root of the
+    /// repository the start is in, or else the start itself
+    top: PathBuf,
+    matchers: HashMap<PathBuf, Gitignore>,
+    ignored_dirs: HashMap<PathBuf, bool>,
+}
+
+impl Ignores {
+    pub fn new(start: &str, respect_ignore: bool, hidden: bool) -> Self {
+        let abs_start = fs::canonicalize(start)
+            .unwrap_or_else(|_| PathBuf::from(start));
+        let top = if respect_ignore {
+            abs_start
+                .ancestors()
+                .find(|dir| dir.join(".git").exists())
+                .unwrap_or(&abs_start)
+                .to_path_buf()
+        } else {
+            abs_start.clone()
+        };
+        let global = if respect_ignore {
+            Gitignore::global().0
+        } else {
+            Gitignore::empty()
+        };
+        Ignores {
+            respect_ignore,
+            hidden,
+            global,
+            start: PathBuf::from(start),
+            abs_start,
+            top,
+            matchers: HashMap::new(),
+            ignored_dirs: HashMap::new(),
+        }
+    }
+
+    /// Whether the entry, or any directory it is in below the starting
+    /// path, is skipped. Starting paths are never skipped.
+    pub fn is_ignored(&mut self, entry: &Entry) -> bool {
+        match entry.path().strip_prefix(&self.start) {
+            Ok(rel) if entry.depth() > 0 => {
+                let path = self.abs_start.join(rel);
+                self.ignored(&path, entry.file_type().is_dir())
+            }
+            _ => false,
+        }
+    }
+
+    fn ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        if path == self.abs_start {
+            return false;
+        }
+        if let Some(&ignored) = self.ignored_dirs.get(path) {
+            return ignored;
+        }
+        let ignored = match path.parent() {
+            Some(parent) => {
+                self.ignored(parent, true) || self.matches(path, is_dir)
+            }
+            None => false,
+        };
+        if is_dir {
+            self.ignored_dirs.insert(path.to_path_buf(), ignored);
+        }
+        ignored
+    }
+
+    /// Whether the entry itself is skipped, regardless of its directory
+    fn matches(&mut self, path: &Path, is_dir: bool) -> bool {
+        let name = path.file_name().unwrap_or_default();
+        if !self.hidden && name.as_encoded_bytes().starts_with(b".") {
+            return true;
+        }
+        if !self.respect_ignore {
+            return false;
+        }
+        if is_dir && name == ".git" {
+            return true;
+        }
+        for dir in path.ancestors().skip(1) {
+            let matched = self.matcher(dir).matched(path, is_dir);
+            if matched.is_ignore() || matched.is_whitelist() {
+                return matched.is_ignore();
+            }
+            if dir == self.top {
+                break;
+            }
+        }
+        self.global.matched(path, is_dir).is_ignore()
+    }
+
+    /// The patterns of the ignore files in a directory
+    fn matcher(&mut self, dir: &Path) -> &Gitignore {
+        self.matchers.entry(dir.to_path_buf()).or_insert_with(|| {
+            let mut builder = GitignoreBuilder::new(dir);
+            for file in IGNORE_FILES {
+                let file = dir.join(file);
+                if file.is_file() {
+                    if let Some(e) = builder.add(&file) {
+                        eprintln!("{}: {}", file.display(), e);
+                    }
+                }
+            }
+            builder.build().unwrap_or_else(|e| {
+                eprintln!("{}: {}", dir.display(), e);
+                Gitignore::empty()
+            })
+        })
+    }
+}
@@ -1,96 +1,266 @@
-use crate::EntryType::*;
-use clap::{Parser, ValueEnum};
+mod action;
+mod dupes;
+mod expr;
+mod ignores;
+mod parallel;
+mod perm;
+mod printf;
+mod walk;
+
+use crate::action::{Action, Context};
+use crate::dupes::{DupeOptions, Duplicates, Resolve};
+use crate::expr::{Bound, Candidate, EntryType, Expr, Size, Test};
+use crate::ignores::Ignores;
+use crate::walk::{Entry, WalkOptions};
+use clap::Parser;
 use regex::Regex;
-use walkdir::{WalkDir, DirEntry};
-use std::error::Error;
+use std::{
+    env, error::Error, ffi::OsString, num::NonZeroUsize, process::ExitCode,
+    time::SystemTime,
+};
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-enum EntryType {
-    Dir,
-    File,
-    Link,
-}
-
-impl std::str::FromStr for EntryType {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "d" => Ok(EntryType::Dir),
-            "f" => Ok(EntryType::File),
-            "l" => Ok(EntryType::Link),
-            _ => Err("invalid entry type"),
-        }
-    }
-}
-
-impl ValueEnum for EntryType {
-    fn value_variants<'a>() -> &'a [Self] {
-        &[Dir, File, Link]
-    }
-
-    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
-        let value = match self {
-            EntryType::Dir => "d",
-            EntryType::File => "f",
-            EntryType::Link => "l",
-        };
-        Some(clap::builder::PossibleValue::new(value))
-    }
-}
+const EXPRESSION_HELP: &str = "\
+Expression:
+  Tests and operators after the paths, evaluated left to right:
+    ( EXPR )   ! EXPR   -not EXPR   EXPR [-a|-and] EXPR   EXPR -o|-or EXPR
+    -true  -false  -name/-iname GLOB  -path/-ipath GLOB
+    -regex/-iregex REGEX  -type d|f|l[,...]  -size [+-]N[ckMG]
+    -mtime/-atime/-ctime [+-]N  -mmin [+-]N  -newer FILE  -empty
+    -user NAME  -group NAME  -uid [+-]N  -gid [+-]N  -nouser  -nogroup
+    -perm [-/]MODE
+  -name matches the file name and -path the whole path against a shell
+  pattern, and -regex matches the whole path against a regular expression.
+  -perm MODE, octal or symbolic like u+w,o=r, matches exactly those
+  permissions, -MODE all of them and /MODE any of them.
+  Actions, which are also true or false:
+    -print  -print0  -printf FORMAT  -delete  -prune
+    -exec COMMAND ;  -exec COMMAND {} +
+  Options of the whole walk:
+    -maxdepth N  -mindepth N  -follow
+  Without an action, matching paths are printed. -exec COMMAND ; replaces
+  each {} with the path and is true if the command succeeds; {} + runs the
+  command on as many paths at once as fit, exiting with an error if it fails.
+  -delete visits entries before their directories and never removes a PATH.
+  -prune skips the contents of a matching directory, unless -delete is used.
+  -printf FORMAT prints %p path, %f name, %h directory, %s size, %m octal
+  and %M symbolic permissions, %u user, %g group, %t modification time,
+  %T@ seconds since the epoch, %d depth, %y type, %l link target and %%,
+  with optional [-][WIDTH][.PRECISION], and escapes like \\n, \\t, \\0NN and
+  \\c to stop.
+  The options above are ANDed with the expression.
+  With --duplicates, the regular files matching the expression, which may
+  not have actions, are compared by size and then by content, and each
+  group of identical files is printed with the first found first.";
 
 #[derive(Parser, Debug)]
-pub struct Config {
+#[command(
+    override_usage = "findr [OPTIONS] [PATH]... [EXPRESSION]",
+    after_help = EXPRESSION_HELP
+)]
+pub struct ConfigArgs {
     #[arg(name = "PATH", default_value = ".")]
     path: Vec<String>,
-    #[arg(short, long = "name", num_args(0..))]
+    /// Follow symbolic links
+    #[arg(short = 'L', long)]
+    follow: bool,
+    /// Skip entries matched by .gitignore, .ignore, .git/info/exclude or
+    /// the global git ignore file, hidden entries and .git directories
+    #[arg(long)]
+    respect_ignore: bool,
+    /// Include hidden entries, even with --respect-ignore
+    #[arg(long, overrides_with = "no_hidden")]
+    hidden: bool,
+    /// Skip hidden entries
+    #[arg(long, overrides_with = "hidden")]
+    no_hidden: bool,
+    /// Read directories with N threads
+    #[arg(short = 'j', long = "jobs", value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+    /// Visit the entries of each directory sorted by name, in the same
+    /// order with any number of threads
+    #[arg(long)]
+    sort: bool,
+    /// Print groups of matching regular files with identical contents
+    #[arg(long)]
+    duplicates: bool,
+    /// Replace each duplicate with a hard link to the first of its group
+    #[arg(long, requires = "duplicates", conflicts_with = "delete_but_first")]
+    hardlink: bool,
+    /// Delete each duplicate but the first of its group
+    #[arg(long, requires = "duplicates")]
+    delete_but_first: bool,
+    /// Carry out --hardlink or --delete-but-first rather than only printing
+    /// what they would do
+    #[arg(long, requires = "duplicates")]
+    force: bool,
+    /// Name matching a regular expression anywhere
+    #[arg(
+        short,
+        long = "name",
+        visible_alias = "name-regex",
+        value_name = "REGEX",
+        num_args(0..)
+    )]
     names: Vec<Regex>,
     #[arg(short = 't', long = "type", num_args(0..))]
     entry_types: Vec<EntryType>,
+    /// Size of N units (512-byte blocks, or c, w, b, k, M, G)
+    #[arg(long, value_name = "[+-]N[ckMG]", allow_hyphen_values = true)]
+    size: Option<Size>,
+    /// Data last modified N days ago
+    #[arg(long, value_name = "[+-]N", allow_hyphen_values = true)]
+    mtime: Option<Bound>,
+    /// Last accessed N days ago
+    #[arg(long, value_name = "[+-]N", allow_hyphen_values = true)]
+    atime: Option<Bound>,
+    /// Status last changed N days ago
+    #[arg(long, value_name = "[+-]N", allow_hyphen_values = true)]
+    ctime: Option<Bound>,
+    /// Data last modified N minutes ago
+    #[arg(long, value_name = "[+-]N", allow_hyphen_values = true)]
+    mmin: Option<Bound>,
+    /// Modified more recently than FILE
+    #[arg(long, value_name = "FILE")]
+    newer: Option<String>,
+    /// Empty regular file or directory
+    #[arg(long)]
+    empty: bool,
+}
+
+#[derive(Debug)]
+pub struct Config {
+    paths: Vec<String>,
+    expr: Expr,
+    walk: WalkOptions,
+    jobs: usize,
+    respect_ignore: bool,
+    hidden: bool,
+    duplicates: Option<DupeOptions>,
 }
 
 pub fn get_args() -> MyResult<Config> {
-    Ok(Config::parse())
+    let args: Vec<OsString> = env::args_os().collect();
+    let start = args
+        .iter()
+        .skip(1)
+        .position(|arg| arg.to_str().is_some_and(expr::starts_expression))
+        .map_or(args.len(), |i| i + 1);
+    let config_args = ConfigArgs::parse_from(&args[..start]);
+    let expr_args = args[start..]
+        .iter()
+        .map(|arg| {
+            arg.to_str().map(String::from).ok_or_else(|| {
+                format!("invalid UTF-8 in \"{}\"", arg.to_string_lossy())
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut exprs: Vec<Expr> = vec![];
+    if !config_args.names.is_empty() {
+        exprs.push(Expr::any(
+            config_args.names.into_iter().map(|re| Test::Name(re).into()),
+        ));
+    }
+    if !config_args.entry_types.is_empty() {
+        exprs.push(Test::Type(config_args.entry_types).into());
+    }
+    if let Some(size) = config_args.size {
+        exprs.push(Test::Size(size).into());
+    }
+    if let Some(bound) = config_args.mtime {
+        exprs.push(Test::mtime(bound).into());
+    }
+    if let Some(bound) = config_args.atime {
+        exprs.push(Test::atime(bound).into());
+    }
+    if let Some(bound) = config_args.ctime {
+        exprs.push(Test::ctime(bound).into());
+    }
+    if let Some(bound) = config_args.mmin {
+        exprs.push(Test::mmin(bound).into());
+    }
+    if let Some(file) = &config_args.newer {
+        exprs.push(Test::newer(file)?.into());
+    }
+    if config_args.empty {
+        exprs.push(Test::Empty.into());
+    }
+    let (parsed, mut walk) = expr::parse(&expr_args)?;
+    exprs.push(parsed);
+    walk.follow |= config_args.follow;
+    walk.sort = config_args.sort;
+
+    let mut expr = Expr::all(exprs);
+    if config_args.duplicates {
+        if expr.has_action() {
+            return Err(From::from("--duplicates cannot be used with actions"));
+        }
+    } else if !expr.has_action() {
+        expr = Expr::all([expr, Expr::Action(Action::Print)]);
+    }
+    walk.contents_first = expr.deletes();
+
+    Ok(Config {
+        paths: config_args.path,
+        expr,
+        walk,
+        jobs: config_args.jobs.map_or(1, NonZeroUsize::get),
+        respect_ignore: config_args.respect_ignore,
+        hidden: config_args.hidden
+            || !(config_args.no_hidden || config_args.respect_ignore),
+        duplicates: config_args.duplicates.then_some(DupeOptions {
+            resolve: if config_args.hardlink {
+                Resolve::Hardlink
+            } else if config_args.delete_but_first {
+                Resolve::Delete
+            } else {
+                Resolve::List
+            },
+            dry_run: !config_args.force,
+        }),
+    })
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let type_filter = |entry: &DirEntry| {
-        config.entry_types.is_empty() || config.entry_types.iter().any(
-            |entry_type| {
-                match entry_type {
-                    Dir => entry.file_type().is_dir(),
-                    File => entry.file_type().is_file(),
-                    Link => entry.file_type().is_symlink(),
+pub fn run(config: Config) -> MyResult<ExitCode> {
+    let now = SystemTime::now();
+    let mut ctx = Context::new();
+    let mut dupes = config.duplicates.map(Duplicates::new);
+    for path in &config.paths {
+        let mut ignores = (config.respect_ignore || !config.hidden)
+            .then(|| Ignores::new(path, config.respect_ignore, config.hidden));
+        let visit = |entry: &Entry| -> MyResult<bool> {
+            if ignores.as_mut().is_some_and(|i| i.is_ignored(entry)) {
+                return Ok(false);
+            }
+            if entry.depth() >= config.walk.min_depth {
+                let candidate = Candidate::new(entry, now);
+                let matched = config.expr.eval(&candidate, &mut ctx);
+                if let Some(dupes) = dupes.as_mut() {
+                    if matched && entry.file_type().is_file() {
+                        dupes.add(entry);
+                    }
+                }
+                if let Some(e) = ctx.take_error() {
+                    return Err(e.into());
                 }
             }
-        )
-    };
-
-    let name_filter = |entry: &DirEntry| {
-        config.names.is_empty() || config.names.iter().any(
-            |re| re.is_match(
-                &entry.file_name().to_string_lossy(),
-            )
-        )
-    };
-
-    for path in config.path {
-        let entries = WalkDir::new(path)
-            .into_iter()
-            .filter_map(|e| match e {
-                Ok(entry) => Some(entry),
-                Err(e) => {
-                    eprintln!("{}", e);
-                    None
-                },
-            })
-            .filter(type_filter)
-            .filter(name_filter)
-            .map(|entry| entry.path().display().to_string())
-            .collect::<Vec<_>>();
-        println!("{}", entries.join("\n"));
+            Ok(!ctx.take_pruned())
+        };
+        let ok = if config.jobs > 1 {
+            parallel::walk(path, &config.walk, config.jobs, visit)?
+        } else {
+            walk::walk(path, &config.walk, visit)?
+        };
+        if !ok {
+            ctx.fail();
+        }
+    }
+    if let Some(dupes) = dupes {
+        if !dupes.finish()? {
+            ctx.fail();
+        }
     }
-    Ok(())
+    Ok(ctx.finish()?)
 }
@@ -1,6 +1,11 @@
-fn main() {
-    if let Err(err) = findr::get_args().and_then(findr::run) {
-        eprintln!("{}", err);
-        std::process::exit(1);
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match findr::get_args().and_then(findr::run) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
     }
 }
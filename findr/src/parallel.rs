@@ -0,0 +1,400 @@
+use crate::{
+    walk::{Entry, WalkOptions},
+    MyResult,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::PathBuf,
+    sync::{mpsc, Arc, Condvar, Mutex},
+    thread::{self, JoinHandle},
+    vec,
+};
+
+/// Walks a tree with `threads` workers reading directories and fetching
+/// the metadata of their entries, while the entries are visited on the
+/// current thread. `visit` returns whether to descend into a directory it
+/// is given first.
+///
+/// Entries are visited as soon as their directory has been read, unless
+/// they are sorted or their contents come first, when they are visited in
+/// the same order as a walk on a single thread. Subdirectories are then
+/// read ahead of being visited, so one that is not descended into may
+/// still have been read. Like a walk on a single thread, the walk returns
+/// whether every entry could be read.
+pub fn walk(
+    path: &str,
+    options: &WalkOptions,
+    threads: usize,
+    mut visit: impl FnMut(&Entry) -> MyResult<bool>,
+) -> MyResult<bool> {
+    let root = match root(path, options.follow) {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return Ok(false);
+        }
+    };
+    let mut pool = Pool::new(threads, options.follow);
+    if options.sort || options.contents_first {
+        in_order(&mut pool, root, options, &mut visit)
+    } else {
+        as_found(&mut pool, root, options, &mut visit)
+    }
+}
+
+fn root(path: &str, follow: bool) -> io::Result<Entry> {
+    let meta = fs::symlink_metadata(path)?;
+    let entry = if follow && meta.file_type().is_symlink() {
+        let file_type = fs::metadata(path)?.file_type();
+        Entry::new(PathBuf::from(path), file_type, 0, true)
+    } else {
+        Entry::new(PathBuf::from(path), meta.file_type(), 0, false)
+    };
+    Ok(entry)
+}
+
+/// Whether the walk goes into an entry, which for a starting path
+/// includes a link to a directory
+fn descends(entry: &Entry, options: &WalkOptions) -> bool {
+    let file_type = entry.file_type();
+    let is_dir = file_type.is_dir()
+        || entry.depth() == 0
+            && file_type.is_symlink()
+            && fs::metadata(entry.path()).is_ok_and(|meta| meta.is_dir());
+    is_dir && options.max_depth.is_none_or(|max| entry.depth() < max)
+}
+
+fn as_found(
+    pool: &mut Pool,
+    root: Entry,
+    options: &WalkOptions,
+    visit: &mut impl FnMut(&Entry) -> MyResult<bool>,
+) -> MyResult<bool> {
+    let mut ok = true;
+    let mut pending = 0;
+    if visit(&root)? && descends(&root, options) {
+        pool.submit(&root, Arc::default());
+        pending += 1;
+    }
+    while pending > 0 {
+        let listing = pool.recv()?;
+        pending -= 1;
+        for entry in listing.entries {
+            match entry {
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ok = false;
+                }
+                Ok(entry) => {
+                    if visit(&entry)? && descends(&entry, options) {
+                        pool.submit(&entry, listing.ancestors.clone());
+                        pending += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(ok)
+}
+
+/// An entry of a directory, or why it could not be read
+type Found = Result<Entry, String>;
+
+/// A directory being visited in order
+struct Frame {
+    dir: Entry,
+    id: usize,
+    /// The entries left to visit, with the jobs reading those that are
+    /// directories, once the directory has been read
+    entries: Option<vec::IntoIter<(Found, Option<usize>)>>,
+}
+
+fn in_order(
+    pool: &mut Pool,
+    root: Entry,
+    options: &WalkOptions,
+    visit: &mut impl FnMut(&Entry) -> MyResult<bool>,
+) -> MyResult<bool> {
+    let mut ok = true;
+    let mut listings = Listings::default();
+    let mut stack = vec![];
+    let descend = options.contents_first || visit(&root)?;
+    if descend && descends(&root, options) {
+        let id = pool.submit(&root, Arc::default());
+        stack.push(Frame {
+            dir: root,
+            id,
+            entries: None,
+        });
+    } else if options.contents_first {
+        visit(&root)?;
+    }
+
+    while let Some(frame) = stack.last_mut() {
+        if frame.entries.is_none() {
+            let listing = listings.wait(pool, frame.id)?;
+            let mut entries = listing.entries;
+            if options.sort {
+                entries.sort_by(|a, b| match (a, b) {
+                    (Ok(a), Ok(b)) => a.file_name().cmp(b.file_name()),
+                    _ => a.is_ok().cmp(&b.is_ok()),
+                });
+            }
+            // Queued last to first, so the first is read first
+            let mut jobs: Vec<_> = entries
+                .iter()
+                .rev()
+                .map(|entry| match entry {
+                    Ok(entry) if descends(entry, options) => {
+                        Some(pool.submit(entry, listing.ancestors.clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            jobs.reverse();
+            let entries: Vec<_> = entries.into_iter().zip(jobs).collect();
+            frame.entries = Some(entries.into_iter());
+        }
+
+        match frame.entries.as_mut().and_then(Iterator::next) {
+            None => {
+                if let Some(frame) = stack.pop() {
+                    if options.contents_first {
+                        visit(&frame.dir)?;
+                    }
+                }
+            }
+            Some((Err(e), _)) => {
+                eprintln!("{}", e);
+                ok = false;
+            }
+            Some((Ok(entry), job)) => {
+                let descend = options.contents_first || visit(&entry)?;
+                match job {
+                    Some(id) if descend => stack.push(Frame {
+                        dir: entry,
+                        id,
+                        entries: None,
+                    }),
+                    Some(id) => listings.discard(id),
+                    None if options.contents_first => {
+                        visit(&entry)?;
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+    Ok(ok)
+}
+
+/// Directories read ahead of being visited
+#[derive(Default)]
+struct Listings {
+    read: HashMap<usize, Listing>,
+    discarded: HashSet<usize>,
+}
+
+impl Listings {
+    /// The entries of the directory read by a job, waiting for it
+    fn wait(&mut self, pool: &Pool, id: usize) -> MyResult<Listing> {
+        loop {
+            if let Some(listing) = self.read.remove(&id) {
+                return Ok(listing);
+            }
+            let listing = pool.recv()?;
+            if !self.discarded.remove(&listing.id) {
+                self.read.insert(listing.id, listing);
+            }
+        }
+    }
+
+    /// Drops the entries of a directory that will not be visited
+    fn discard(&mut self, id: usize) {
+        if self.read.remove(&id).is_none() {
+            self.discarded.insert(id);
+        }
+    }
+}
+
+/// A directory to be read by a worker
+struct Job {
+    id: usize,
+    dir: PathBuf,
+    depth: usize,
+    /// The canonical paths of the directories above, when following links
+    ancestors: Arc<Vec<PathBuf>>,
+}
+
+/// The entries of a directory read by a worker
+struct Listing {
+    id: usize,
+    /// The canonical paths of the directory and those above, when
+    /// following links
+    ancestors: Arc<Vec<PathBuf>>,
+    entries: Vec<Found>,
+}
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: Vec<Job>,
+    closed: bool,
+}
+
+/// Worker threads taking the most recently queued directory to read
+struct Pool {
+    queue: Arc<Queue>,
+    listings: mpsc::Receiver<Listing>,
+    workers: Vec<JoinHandle<()>>,
+    next_id: usize,
+}
+
+impl Pool {
+    fn new(threads: usize, follow: bool) -> Self {
+        let queue = Arc::new(Queue::default());
+        let (tx, listings) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    while let Some(job) = queue.take() {
+                        if tx.send(read(job, follow)).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        Pool {
+            queue,
+            listings,
+            workers,
+            next_id: 0,
+        }
+    }
+
+    /// Queues a directory to be read, returning the id of its job
+    fn submit(&mut self, dir: &Entry, ancestors: Arc<Vec<PathBuf>>) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        let job = Job {
+            id,
+            dir: dir.path().to_path_buf(),
+            depth: dir.depth(),
+            ancestors,
+        };
+        self.queue.lock().jobs.push(job);
+        self.queue.ready.notify_one();
+        id
+    }
+
+    fn recv(&self) -> MyResult<Listing> {
+        self.listings
+            .recv()
+            .map_err(|_| From::from("a directory reader thread failed"))
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        {
+            let mut state = self.queue.lock();
+            state.jobs.clear();
+            state.closed = true;
+        }
+        self.queue.ready.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Queue {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The next job, waiting for one until the queue is closed
+    fn take(&self) -> Option<Job> {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return None;
+            }
+            if let Some(job) = state.jobs.pop() {
+                return Some(job);
+            }
+            state = self.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+}
+
+/// Reads the entries of a directory and their metadata
+fn read(job: Job, follow: bool) -> Listing {
+    let mut listing = Listing {
+        id: job.id,
+        ancestors: job.ancestors,
+        entries: vec![],
+    };
+    if follow {
+        match fs::canonicalize(&job.dir) {
+            Ok(dir) => {
+                let ancestors = &listing.ancestors;
+                if let Some(ancestor) = ancestors.iter().find(|a| **a == dir) {
+                    listing.entries.push(Err(format!(
+                        "File system loop found: {} points to an ancestor {}",
+                        job.dir.display(),
+                        ancestor.display()
+                    )));
+                    return listing;
+                }
+                Arc::make_mut(&mut listing.ancestors).push(dir);
+            }
+            Err(e) => {
+                let error = format!("{}: {}", job.dir.display(), e);
+                listing.entries.push(Err(error));
+                return listing;
+            }
+        }
+    }
+    let error = |e: io::Error| format!("{}: {}", job.dir.display(), e);
+    match fs::read_dir(&job.dir) {
+        Ok(dir) => {
+            for dir_entry in dir {
+                let entry = dir_entry.and_then(|dir_entry| {
+                    child(dir_entry, job.depth + 1, follow)
+                });
+                listing.entries.push(entry.map_err(error));
+            }
+        }
+        Err(e) => listing.entries.push(Err(error(e))),
+    }
+    listing
+}
+
+fn child(
+    dir_entry: fs::DirEntry,
+    depth: usize,
+    follow: bool,
+) -> io::Result<Entry> {
+    let path = dir_entry.path();
+    let mut file_type = dir_entry.file_type()?;
+    let mut followed = false;
+    if follow && file_type.is_symlink() {
+        if let Ok(meta) = fs::metadata(&path) {
+            file_type = meta.file_type();
+            followed = true;
+        }
+    }
+    let entry = Entry::new(path, file_type, depth, followed);
+    entry.metadata();
+    Ok(entry)
+}
@@ -0,0 +1,111 @@
+use std::fs::Metadata;
+
+/// A `-perm` argument: permission bits and how they must be set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Perm {
+    bits: u32,
+    kind: PermKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PermKind {
+    /// `MODE`: exactly these bits
+    Exactly,
+    /// `-MODE`: at least all of these bits
+    All,
+    /// `/MODE`: any of these bits
+    Any,
+}
+
+impl Perm {
+    pub fn matches(&self, mode: u32) -> bool {
+        let mode = mode & 0o7777;
+        match self.kind {
+            PermKind::Exactly => mode == self.bits,
+            PermKind::All => mode & self.bits == self.bits,
+            PermKind::Any => self.bits == 0 || mode & self.bits != 0,
+        }
+    }
+}
+
+impl std::str::FromStr for Perm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, mode) = if let Some(mode) = s.strip_prefix('-') {
+            (PermKind::All, mode)
+        } else if let Some(mode) = s.strip_prefix('/') {
+            (PermKind::Any, mode)
+        } else {
+            (PermKind::Exactly, s)
+        };
+        let bits = if mode.starts_with(|c: char| c.is_ascii_digit()) {
+            u32::from_str_radix(mode, 8).ok().filter(|&bits| bits <= 0o7777)
+        } else {
+            symbolic(mode)
+        };
+        bits.map(|bits| Perm { bits, kind })
+            .ok_or_else(|| format!("invalid mode \"{}\"", s))
+    }
+}
+
+/// The bits of a symbolic mode like `u+rw,go=r` applied to no bits
+fn symbolic(mode: &str) -> Option<u32> {
+    let mut bits = 0;
+    for clause in mode.split(',') {
+        let op_at = clause.find(['+', '-', '='])?;
+        let mut who = 0;
+        for c in clause[..op_at].chars() {
+            who |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None,
+            };
+        }
+        if who == 0 {
+            who = 0o7777;
+        }
+        // Each operator applies the permissions up to the next one
+        let mut rest = &clause[op_at..];
+        while let Some(op) = rest.chars().next() {
+            let perms_end = rest[1..]
+                .find(['+', '-', '='])
+                .map_or(rest.len(), |i| i + 1);
+            let mut perms = 0;
+            for c in rest[1..perms_end].chars() {
+                perms |= match c {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' | 'X' => 0o111,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    _ => return None,
+                };
+            }
+            match op {
+                '+' => bits |= who & perms,
+                '-' => bits &= !(who & perms),
+                _ => bits = (bits & !who) | (who & perms),
+            }
+            rest = &rest[perms_end..];
+        }
+    }
+    Some(bits)
+}
+
+#[cfg(unix)]
+pub fn mode(meta: &Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    meta.mode()
+}
+
+#[cfg(not(unix))]
+pub fn mode(meta: &Metadata) -> u32 {
+    if meta.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
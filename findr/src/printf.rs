@@ -0,0 +1,349 @@
+use crate::{perm::mode, walk::Entry};
+use chrono::{DateTime, Local};
+use std::{
+    fs::{self, FileType, Metadata},
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// A `-printf` format
+#[derive(Debug, Clone)]
+pub struct Format {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Literal(Vec<u8>),
+    Directive {
+        /// Pad on the right rather than the left
+        left: bool,
+        width: usize,
+        /// Most bytes to print
+        precision: Option<usize>,
+        kind: Directive,
+    },
+    /// `\c`: print nothing more
+    Stop,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Directive {
+    Path,
+    Name,
+    Dir,
+    Size,
+    Mode,
+    SymbolicMode,
+    User,
+    Group,
+    Time,
+    Timestamp,
+    Depth,
+    Type,
+    Target,
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |what: String| {
+            format!("Invalid -printf {} in \"{}\"", what, s)
+        };
+        let mut pieces = vec![];
+        let mut literal = vec![];
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match escape(&mut chars).map_err(invalid)? {
+                    Some(byte) => literal.push(byte),
+                    None => {
+                        pieces.push(Piece::Literal(std::mem::take(
+                            &mut literal,
+                        )));
+                        pieces.push(Piece::Stop);
+                        break;
+                    }
+                },
+                '%' if chars.next_if_eq(&'%').is_some() => literal.push(b'%'),
+                '%' => {
+                    let directive = directive(&mut chars).map_err(invalid)?;
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    pieces.push(directive);
+                }
+                c => literal
+                    .extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        pieces.push(Piece::Literal(literal));
+        pieces.retain(|p| !matches!(p, Piece::Literal(l) if l.is_empty()));
+        Ok(Format { pieces })
+    }
+}
+
+/// The byte of an escape after its `\\`, or `None` for `\\c`
+fn escape(chars: &mut Chars) -> Result<Option<u8>, String> {
+    let c = chars.next().ok_or("escape \"\\\"")?;
+    let byte = match c {
+        'a' => 7,
+        'b' => 8,
+        'c' => return Ok(None),
+        'f' => 12,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 11,
+        '\\' => b'\\',
+        '0'..='7' => {
+            let mut n = c.to_digit(8).unwrap_or_default();
+            for _ in 0..2 {
+                match chars.peek().and_then(|c| c.to_digit(8)) {
+                    Some(digit) => {
+                        n = n * 8 + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            u8::try_from(n).map_err(|_| format!("escape \"\\{:o}\"", n))?
+        }
+        _ => return Err(format!("escape \"\\{}\"", c)),
+    };
+    Ok(Some(byte))
+}
+
+/// A directive after its `%`, such as `-10.4p`
+fn directive(chars: &mut Chars) -> Result<Piece, String> {
+    let left = chars.next_if_eq(&'-').is_some();
+    let width = number(chars);
+    let precision = chars.next_if_eq(&'.').map(|_| number(chars));
+    let kind = match chars.next() {
+        Some('p') => Directive::Path,
+        Some('f') => Directive::Name,
+        Some('h') => Directive::Dir,
+        Some('s') => Directive::Size,
+        Some('m') => Directive::Mode,
+        Some('M') => Directive::SymbolicMode,
+        Some('u') => Directive::User,
+        Some('g') => Directive::Group,
+        Some('t') => Directive::Time,
+        Some('T') if chars.next_if_eq(&'@').is_some() => Directive::Timestamp,
+        Some('d') => Directive::Depth,
+        Some('y') => Directive::Type,
+        Some('l') => Directive::Target,
+        Some(c) => return Err(format!("directive \"%{}\"", c)),
+        None => return Err("directive \"%\"".to_string()),
+    };
+    Ok(Piece::Directive {
+        left,
+        width,
+        precision,
+        kind,
+    })
+}
+
+fn number(chars: &mut Chars) -> usize {
+    let mut n: usize = 0;
+    while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+        n = n.saturating_mul(10).saturating_add(digit as usize);
+        chars.next();
+    }
+    n
+}
+
+impl Format {
+    pub fn write(&self, entry: &Entry, out: &mut impl Write) -> io::Result<()> {
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(bytes) => out.write_all(bytes)?,
+                Piece::Directive {
+                    left,
+                    width,
+                    precision,
+                    kind,
+                } => {
+                    let mut value = kind.expand(entry);
+                    if let Some(precision) = precision {
+                        value.truncate(*precision);
+                    }
+                    let pad = vec![b' '; width.saturating_sub(value.len())];
+                    if *left {
+                        out.write_all(&value)?;
+                        out.write_all(&pad)?;
+                    } else {
+                        out.write_all(&pad)?;
+                        out.write_all(&value)?;
+                    }
+                }
+                Piece::Stop => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Directive {
+    fn expand(&self, entry: &Entry) -> Vec<u8> {
+        let path = entry.path().as_os_str().as_encoded_bytes();
+        let meta = entry.metadata();
+        match self {
+            Directive::Path => path.to_vec(),
+            Directive::Name => match path.iter().rposition(|&b| b == b'/') {
+                Some(i) if i + 1 < path.len() => path[i + 1..].to_vec(),
+                _ => path.to_vec(),
+            },
+            Directive::Dir => match path.iter().rposition(|&b| b == b'/') {
+                Some(0) if path.len() == 1 => vec![],
+                Some(i) => path[..i].to_vec(),
+                None => b".".to_vec(),
+            },
+            Directive::Size => meta
+                .map_or(String::new(), |meta| meta.len().to_string())
+                .into(),
+            Directive::Mode => meta
+                .map_or(String::new(), |meta| {
+                    format!("{:o}", mode(meta) & 0o7777)
+                })
+                .into(),
+            Directive::SymbolicMode => meta
+                .map_or(String::new(), |meta| {
+                    format!(
+                        "{}{}",
+                        type_char(entry.file_type(), '-'),
+                        symbolic_mode(mode(meta))
+                    )
+                })
+                .into(),
+            Directive::User => meta.map_or(String::new(), user).into(),
+            Directive::Group => meta.map_or(String::new(), group).into(),
+            Directive::Time => meta
+                .and_then(|meta| meta.modified().ok())
+                .map_or(String::new(), |time| {
+                    let local: DateTime<Local> = DateTime::from(time);
+                    format!(
+                        "{}.{:09}0 {}",
+                        local.format("%a %b %e %H:%M:%S"),
+                        local.timestamp_subsec_nanos(),
+                        local.format("%Y")
+                    )
+                })
+                .into(),
+            Directive::Timestamp => meta
+                .and_then(|meta| meta.modified().ok())
+                .map_or(String::new(), timestamp)
+                .into(),
+            Directive::Depth => entry.depth().to_string().into(),
+            Directive::Type => {
+                type_char(entry.file_type(), 'f').to_string().into()
+            }
+            Directive::Target => {
+                if entry.file_type().is_symlink() {
+                    fs::read_link(entry.path())
+                        .map(|target| {
+                            target.as_os_str().as_encoded_bytes().to_vec()
+                        })
+                        .unwrap_or_default()
+                } else {
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
+/// Seconds since the epoch with ten decimal places, as find prints them
+fn timestamp(time: SystemTime) -> String {
+    let (sign, since) = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => ("", since),
+        Err(e) => ("-", e.duration()),
+    };
+    format!("{}{}.{:09}0", sign, since.as_secs(), since.subsec_nanos())
+}
+
+/// The letter `ls` and find use for a file type, with `file` for a
+/// regular file
+fn type_char(file_type: FileType, file: char) -> char {
+    if file_type.is_dir() {
+        return 'd';
+    }
+    if file_type.is_symlink() {
+        return 'l';
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+        if file_type.is_fifo() {
+            return 'p';
+        }
+        if file_type.is_socket() {
+            return 's';
+        }
+        if file_type.is_block_device() {
+            return 'b';
+        }
+        if file_type.is_char_device() {
+            return 'c';
+        }
+    }
+    if file_type.is_file() {
+        file
+    } else {
+        'U'
+    }
+}
+
+/// Permissions as `ls -l` shows them, such as `rwxr-sr-t`
+fn symbolic_mode(mode: u32) -> String {
+    let triple = |shift: u32, special: u32, set: char| {
+        let bits = mode >> shift;
+        let execute = match (bits & 0o1 != 0, mode & special != 0) {
+            (true, true) => set,
+            (false, true) => set.to_ascii_uppercase(),
+            (true, false) => 'x',
+            (false, false) => '-',
+        };
+        format!(
+            "{}{}{}",
+            if bits & 0o4 == 0 { '-' } else { 'r' },
+            if bits & 0o2 == 0 { '-' } else { 'w' },
+            execute,
+        )
+    };
+    format!(
+        "{}{}{}",
+        triple(6, 0o4000, 's'),
+        triple(3, 0o2000, 's'),
+        triple(0, 0o1000, 't'),
+    )
+}
+
+#[cfg(unix)]
+fn user(meta: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    let uid = meta.uid();
+    uzers::get_user_by_uid(uid)
+        .map(|u| u.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| uid.to_string())
+}
+
+#[cfg(not(unix))]
+fn user(_meta: &Metadata) -> String {
+    String::new()
+}
+
+#[cfg(unix)]
+fn group(meta: &Metadata) -> String {
+    use std::os::unix::fs::MetadataExt;
+    let gid = meta.gid();
+    uzers::get_group_by_gid(gid)
+        .map(|g| g.name().to_string_lossy().into_owned())
+        .unwrap_or_else(|| gid.to_string())
+}
+
+#[cfg(not(unix))]
+fn group(_meta: &Metadata) -> String {
+    String::new()
+}
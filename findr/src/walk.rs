@@ -0,0 +1,128 @@
+use crate::MyResult;
+use std::{
+    cell::OnceCell,
+    ffi::OsStr,
+    fs::{self, FileType, Metadata},
+    path::{Path, PathBuf},
+};
+use walkdir::{DirEntry, WalkDir};
+
+/// How the directory trees are walked
+#[derive(Debug, Default)]
+pub struct WalkOptions {
+    pub min_depth: usize,
+    pub max_depth: Option<usize>,
+    pub follow: bool,
+    /// Visit the entries of each directory sorted by name
+    pub sort: bool,
+    /// Visit the entries of a directory before the directory itself
+    pub contents_first: bool,
+}
+
+/// An entry found in a walk, whose metadata is fetched at most once
+#[derive(Debug)]
+pub struct Entry {
+    path: PathBuf,
+    file_type: FileType,
+    depth: usize,
+    /// Whether the entry is a symbolic link that was followed
+    followed: bool,
+    meta: OnceCell<Option<Metadata>>,
+}
+
+impl Entry {
+    pub fn new(
+        path: PathBuf,
+        file_type: FileType,
+        depth: usize,
+        followed: bool,
+    ) -> Self {
+        Entry {
+            path,
+            file_type,
+            depth,
+            followed,
+            meta: OnceCell::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> &OsStr {
+        self.path.file_name().unwrap_or(self.path.as_os_str())
+    }
+
+    /// The type of the entry, or of its target if it was followed
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// How far below its starting path the entry is
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.meta
+            .get_or_init(|| {
+                if self.followed {
+                    fs::metadata(&self.path).ok()
+                } else {
+                    fs::symlink_metadata(&self.path).ok()
+                }
+            })
+            .as_ref()
+    }
+}
+
+impl From<DirEntry> for Entry {
+    fn from(entry: DirEntry) -> Self {
+        let followed =
+            entry.path_is_symlink() && !entry.file_type().is_symlink();
+        Entry::new(
+            entry.path().to_path_buf(),
+            entry.file_type(),
+            entry.depth(),
+            followed,
+        )
+    }
+}
+
+/// Walks a tree on the current thread, visiting each entry. `visit`
+/// returns whether to descend into a directory it is given first. Entries
+/// that cannot be read are reported, and the walk returns whether there
+/// were none.
+pub fn walk(
+    path: &str,
+    options: &WalkOptions,
+    mut visit: impl FnMut(&Entry) -> MyResult<bool>,
+) -> MyResult<bool> {
+    let mut walk = WalkDir::new(path)
+        .contents_first(options.contents_first)
+        .follow_links(options.follow);
+    if let Some(max_depth) = options.max_depth {
+        walk = walk.max_depth(max_depth);
+    }
+    if options.sort {
+        walk = walk.sort_by_file_name();
+    }
+    let mut ok = true;
+    let mut entries = walk.into_iter();
+    while let Some(entry) = entries.next() {
+        let entry = match entry {
+            Ok(entry) => Entry::from(entry),
+            Err(e) => {
+                eprintln!("{}", e);
+                ok = false;
+                continue;
+            }
+        };
+        let descend = visit(&entry)?;
+        if !descend && !options.contents_first && entry.file_type().is_dir() {
+            entries.skip_current_dir();
+        }
+    }
+    Ok(ok)
+}
@@ -30,7 +30,7 @@ fn skips_bad_dir() -> TestResult {
     Command::cargo_bin(PRG)?
         .arg(&bad)
         .assert()
-        .success()
+        .failure()
         .stderr(predicate::str::is_match(expected)?);
     Ok(())
 }
@@ -302,7 +302,7 @@ fn unreadable_dir() -> TestResult {
     let cmd = Command::cargo_bin(PRG)?
         .arg("tests/inputs")
         .assert()
-        .success();
+        .failure();
     fs::remove_dir(dirname)?;
 
     let out = cmd.get_output();
@@ -316,3 +316,1014 @@ fn unreadable_dir() -> TestResult {
     assert!(stderr.contains("cant-touch-this: Permission denied"));
     Ok(())
 }
+
+// --------------------------------------------------
+fn run_in(dir: &Path, args: &[&str], expected: &[&str]) -> TestResult {
+    check_in(Command::cargo_bin(PRG)?, dir, args, expected)
+}
+
+// --------------------------------------------------
+fn check_in(
+    mut cmd: Command,
+    dir: &Path,
+    args: &[&str],
+    expected: &[&str],
+) -> TestResult {
+    let root = dir.to_str().unwrap();
+    let cmd = cmd
+        .arg(root)
+        .args(args)
+        .assert()
+        .success();
+    let out = cmd.get_output();
+    let stdout = String::from_utf8(out.stdout.clone())?;
+    let mut lines: Vec<String> = stdout
+        .split('\n')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.replacen(root, "", 1))
+        .collect();
+    lines.sort();
+
+    let mut expected: Vec<&str> = expected.to_vec();
+    expected.sort();
+    assert_eq!(lines, expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+fn sized_files() -> Result<tempfile::TempDir, std::io::Error> {
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("empty.txt"), "")?;
+    fs::write(dir.path().join("small.txt"), "x".repeat(100))?;
+    fs::write(dir.path().join("large.txt"), "x".repeat(2000))?;
+    fs::create_dir(dir.path().join("empty_dir"))?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+fn size_more_than() -> TestResult {
+    let dir = sized_files()?;
+    run_in(dir.path(), &["--size", "+1k", "-t", "f"], &["/large.txt"])
+}
+
+// --------------------------------------------------
+#[test]
+fn size_less_than() -> TestResult {
+    let dir = sized_files()?;
+    run_in(dir.path(), &["--size", "-1k", "-t", "f"], &["/empty.txt"])
+}
+
+// --------------------------------------------------
+#[test]
+fn size_exact_bytes() -> TestResult {
+    let dir = sized_files()?;
+    run_in(dir.path(), &["--size", "100c"], &["/small.txt"])
+}
+
+// --------------------------------------------------
+#[test]
+fn empty() -> TestResult {
+    let dir = sized_files()?;
+    run_in(dir.path(), &["--empty"], &["/empty.txt", "/empty_dir"])
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_size() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--size", "3x"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid size unit \"x\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+fn aged_files() -> Result<tempfile::TempDir, std::io::Error> {
+    let dir = tempfile::tempdir()?;
+    let ten_days = std::time::Duration::from_secs(10 * 24 * 60 * 60);
+    fs::write(dir.path().join("new.txt"), "new")?;
+    fs::File::create(dir.path().join("old.txt"))?
+        .set_modified(std::time::SystemTime::now() - ten_days)?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+fn mtime() -> TestResult {
+    let dir = aged_files()?;
+    run_in(dir.path(), &["--mtime", "+5"], &["/old.txt"])?;
+    run_in(dir.path(), &["--mtime", "10", "-t", "f"], &["/old.txt"])?;
+    run_in(dir.path(), &["--mtime", "-5", "-t", "f"], &["/new.txt"])
+}
+
+// --------------------------------------------------
+#[test]
+fn mmin() -> TestResult {
+    let dir = aged_files()?;
+    run_in(dir.path(), &["--mmin", "+60"], &["/old.txt"])?;
+    run_in(dir.path(), &["--mmin", "-60", "-t", "f"], &["/new.txt"])
+}
+
+// --------------------------------------------------
+#[test]
+fn newer() -> TestResult {
+    let dir = aged_files()?;
+    let old = dir.path().join("old.txt");
+    run_in(
+        dir.path(),
+        &["--newer", old.to_str().unwrap(), "-t", "f"],
+        &["/new.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_not_type_d() -> TestResult {
+    run(
+        &["tests/inputs/a", "!", "-type", "d"],
+        "tests/expected/expr_not_type_d_path_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_or() -> TestResult {
+    run(
+        &["tests/inputs/a", "-type", "d", "-o", "-size", "-1"],
+        "tests/expected/expr_type_d_or_size_path_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_parens() -> TestResult {
+    run(
+        &[
+            "tests/inputs/a",
+            "(",
+            "-type",
+            "f",
+            "-o",
+            "-type",
+            "l",
+            ")",
+            "-a",
+            "-not",
+            "-empty",
+        ],
+        "tests/expected/expr_parens_path_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_precedence() -> TestResult {
+    let dir = sized_files()?;
+    // -a binds tighter than -o
+    run_in(
+        dir.path(),
+        &["-type", "d", "-o", "-type", "f", "-size", "+1k"],
+        &["", "/empty_dir", "/large.txt"],
+    )?;
+    run_in(
+        dir.path(),
+        &["(", "-type", "d", "-o", "-type", "f", ")", "-size", "-1"],
+        &["/empty.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn expr_with_options() -> TestResult {
+    let dir = sized_files()?;
+    run_in(
+        dir.path(),
+        &["-t", "f", "-not", "-name", "[el]*"],
+        &["/small.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unmatched_paren() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["(", "-type", "f"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing \")\""));
+    Command::cargo_bin(PRG)?
+        .args(["-type", "f", ")"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("unmatched \")\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_argument() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-type", "f", "-o"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected an expression"));
+    Command::cargo_bin(PRG)?
+        .args(["-size"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing argument to \"-size\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn print0() -> TestResult {
+    let dir = sized_files()?;
+    let root = dir.path().to_str().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "small*", "-print0"])
+        .assert()
+        .success()
+        .stdout(format!("{}/small.txt\0", root));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn explicit_print() -> TestResult {
+    let dir = sized_files()?;
+    run_in(
+        dir.path(),
+        &["-name", "empty*", "-print", "-o", "-name", "large*", "-print"],
+        &["/empty.txt", "/empty_dir", "/large.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn exec_each() -> TestResult {
+    let dir = sized_files()?;
+    run_in(
+        dir.path(),
+        &["-type", "f", "-exec", "echo", "file:{}", ";"],
+        &["file:/empty.txt", "file:/large.txt", "file:/small.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn exec_as_test() -> TestResult {
+    let dir = sized_files()?;
+    run_in(
+        dir.path(),
+        &["-type", "f", "-exec", "test", "-s", "{}", ";", "-print"],
+        &["/large.txt", "/small.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn exec_batch() -> TestResult {
+    let dir = sized_files()?;
+    let root = dir.path().to_str().unwrap();
+    let cmd = Command::cargo_bin(PRG)?
+        .args([root, "-type", "f", "-exec", "echo", "files:", "{}", "+"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(cmd.get_output().stdout.clone())?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("files: "));
+    assert_eq!(lines[0].split(' ').count(), 4);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(not(windows))]
+fn exec_batch_fails() -> TestResult {
+    let dir = sized_files()?;
+    Command::cargo_bin(PRG)?
+        .args([dir.path().to_str().unwrap(), "-exec", "false", "{}", "+"])
+        .assert()
+        .failure()
+        .stdout("");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_missing_exec_terminator() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-exec", "echo", "{}"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing argument to \"-exec\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete() -> TestResult {
+    let dir = sized_files()?;
+    fs::create_dir(dir.path().join("sub"))?;
+    fs::write(dir.path().join("sub/small.txt"), "x")?;
+    run_in(
+        dir.path(),
+        &["-name", "s[mu]*", "-delete", "-print"],
+        &["/small.txt", "/sub/small.txt", "/sub"],
+    )?;
+    assert!(!dir.path().join("sub").exists());
+    assert!(dir.path().join("large.txt").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn delete_refuses_start_path() -> TestResult {
+    let dir = sized_files()?;
+    let root = dir.path().join("empty_dir");
+    Command::cargo_bin(PRG)?
+        .args([root.to_str().unwrap(), "-delete"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("refusing to delete"));
+    assert!(root.exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+fn nested_dirs() -> Result<tempfile::TempDir, std::io::Error> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir_all(dir.path().join("a/b"))?;
+    fs::create_dir_all(dir.path().join("skip/deep"))?;
+    fs::write(dir.path().join("top.txt"), "")?;
+    fs::write(dir.path().join("a/mid.txt"), "")?;
+    fs::write(dir.path().join("a/b/low.txt"), "")?;
+    fs::write(dir.path().join("skip/deep/hidden.txt"), "")?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+fn maxdepth() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-maxdepth", "1"],
+        &["", "/a", "/skip", "/top.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn mindepth() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-mindepth", "3", "-type", "f"],
+        &["/a/b/low.txt", "/skip/deep/hidden.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-name", "skip", "-prune", "-o", "-type", "f", "-print"],
+        &["/a/b/low.txt", "/a/mid.txt", "/top.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn prune_prints_by_default() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-mindepth", "1", "-name", "[as]*", "-prune"],
+        &["/a", "/skip"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_depth() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-maxdepth", "-1"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "invalid argument \"-1\" to \"-maxdepth\"",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links() -> TestResult {
+    let dir = nested_dirs()?;
+    std::os::unix::fs::symlink(
+        dir.path().join("a/b"),
+        dir.path().join("link"),
+    )?;
+    let expected = ["/a/b/low.txt", "/a/mid.txt", "/link/low.txt"];
+    run_in(dir.path(), &["-L", "-name", "[lm]*.txt"], &expected)?;
+    run_in(dir.path(), &["-follow", "-name", "[lm]*.txt"], &expected)?;
+    run_in(
+        dir.path(),
+        &["-name", "[lm]*.txt"],
+        &["/a/b/low.txt", "/a/mid.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn follow_links_loop() -> TestResult {
+    let dir = nested_dirs()?;
+    std::os::unix::fs::symlink(
+        dir.path().join("a"),
+        dir.path().join("a/b/up"),
+    )?;
+    Command::cargo_bin(PRG)?
+        .args([dir.path().to_str().unwrap(), "-L", "-name", "low*"])
+        .assert()
+        .stdout(predicate::str::contains("/a/b/low.txt"))
+        .stderr(predicate::str::contains("loop"));
+    Ok(())
+}
+
+// --------------------------------------------------
+fn ignored_files() -> Result<tempfile::TempDir, std::io::Error> {
+    let dir = tempfile::tempdir()?;
+    let repo = dir.path().join("repo");
+    fs::create_dir_all(repo.join(".git/info"))?;
+    fs::write(repo.join(".git/info/exclude"), "*.bak\n")?;
+    fs::write(repo.join(".gitignore"), "target/\n*.log\n")?;
+    fs::write(repo.join(".ignore"), "!keep.log\n")?;
+    fs::write(repo.join(".env"), "")?;
+    fs::write(repo.join("main.rs"), "")?;
+    fs::write(repo.join("debug.log"), "")?;
+    fs::write(repo.join("keep.log"), "")?;
+    fs::write(repo.join("old.bak"), "")?;
+    fs::write(repo.join("scratch.tmp"), "")?;
+    fs::create_dir_all(repo.join("target/debug"))?;
+    fs::write(repo.join("target/debug/findr"), "")?;
+    fs::create_dir_all(repo.join("src"))?;
+    fs::write(repo.join("src/.gitignore"), "generated.rs\n")?;
+    fs::write(repo.join("src/lib.rs"), "")?;
+    fs::write(repo.join("src/generated.rs"), "")?;
+    fs::create_dir_all(dir.path().join("config/git"))?;
+    fs::write(dir.path().join("config/git/ignore"), "*.tmp\n")?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+fn isolated(dir: &Path) -> Result<Command, Box<dyn std::error::Error>> {
+    let mut cmd = Command::cargo_bin(PRG)?;
+    cmd.env("HOME", dir).env("XDG_CONFIG_HOME", dir.join("config"));
+    Ok(cmd)
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_ignore() -> TestResult {
+    let dir = ignored_files()?;
+    check_in(
+        isolated(dir.path())?,
+        &dir.path().join("repo"),
+        &["--respect-ignore", "-type", "f"],
+        &["/keep.log", "/main.rs", "/src/lib.rs"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_ignore_below_root() -> TestResult {
+    let dir = ignored_files()?;
+    fs::write(dir.path().join("repo/src/debug.log"), "")?;
+    check_in(
+        isolated(dir.path())?,
+        &dir.path().join("repo/src"),
+        &["--respect-ignore", "-type", "f"],
+        &["/lib.rs"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn respect_ignore_hidden() -> TestResult {
+    let dir = ignored_files()?;
+    check_in(
+        isolated(dir.path())?,
+        &dir.path().join("repo"),
+        &["--respect-ignore", "--hidden", "-maxdepth", "1", "-name", ".*"],
+        &["/.env", "/.gitignore", "/.ignore"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn no_hidden() -> TestResult {
+    let dir = ignored_files()?;
+    check_in(
+        isolated(dir.path())?,
+        &dir.path().join("repo"),
+        &["--no-hidden", "-type", "f"],
+        &[
+            "/debug.log",
+            "/keep.log",
+            "/main.rs",
+            "/old.bak",
+            "/scratch.tmp",
+            "/src/generated.rs",
+            "/src/lib.rs",
+            "/target/debug/findr",
+        ],
+    )
+}
+
+// --------------------------------------------------
+fn stdout_of(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::cargo_bin(PRG)?.args(args).output()?;
+    assert!(output.status.success());
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+// --------------------------------------------------
+#[test]
+fn parallel_sorted() -> TestResult {
+    let dir = nested_dirs()?;
+    let root = dir.path().to_str().unwrap();
+    let expected = stdout_of(&[root, "--sort"])?;
+    assert_eq!(stdout_of(&[root, "-j", "4", "--sort"])?, expected);
+    let lines: Vec<&str> = expected.lines().collect();
+    let mut sorted = lines.clone();
+    sorted.sort();
+    assert_eq!(lines, sorted);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn parallel_as_found() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-j", "3", "-type", "f"],
+        &["/a/b/low.txt", "/a/mid.txt", "/skip/deep/hidden.txt", "/top.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn parallel_prune_and_depth() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &[
+            "-j", "2", "-maxdepth", "2", "-name", "skip", "-prune", "-o",
+            "-type", "f", "-print",
+        ],
+        &["/a/mid.txt", "/top.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn parallel_delete() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &[
+            "-j", "2", "-mindepth", "1", "-path", "*/skip*",
+            "-delete", "-print",
+        ],
+        &["/skip", "/skip/deep", "/skip/deep/hidden.txt"],
+    )?;
+    assert!(!dir.path().join("skip").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_jobs() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-j", "0"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value '0'"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn glob_name_a() -> TestResult {
+    run(&["tests/inputs", "-name", "a*"], "tests/expected/name_a.txt")
+}
+
+// --------------------------------------------------
+#[test]
+fn glob_name_txt_path_a_d() -> TestResult {
+    run(
+        &["tests/inputs/a", "tests/inputs/d", "-name", "*.txt"],
+        "tests/expected/name_txt_path_a_d.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn glob_type_f_name_a() -> TestResult {
+    run(
+        &["tests/inputs", "-type", "f", "-name", "a*"],
+        "tests/expected/type_f_name_a.txt",
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn iname() -> TestResult {
+    let dir = sized_files()?;
+    run_in(
+        dir.path(),
+        &["-iname", "[SL]*.TXT"],
+        &["/large.txt", "/small.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn path_glob() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-path", "*/a/*", "-type", "f"],
+        &["/a/b/low.txt", "/a/mid.txt"],
+    )?;
+    run_in(dir.path(), &["-ipath", "*/A/B"], &["/a/b"])
+}
+
+// --------------------------------------------------
+#[test]
+fn regex_whole_path() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(dir.path(), &["-regex", "mid"], &[])?;
+    run_in(
+        dir.path(),
+        &["-regex", ".*/(low|mid)\\.txt"],
+        &["/a/b/low.txt", "/a/mid.txt"],
+    )?;
+    run_in(dir.path(), &["-iregex", ".*/TOP\\..*"], &["/top.txt"])
+}
+
+// --------------------------------------------------
+#[test]
+fn name_regex() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["--name-regex", "low|mid"],
+        &["/a/b/low.txt", "/a/mid.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_regex() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-regex", "*.csv"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid -regex \"*.csv\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf() -> TestResult {
+    let dir = sized_files()?;
+    let root = dir.path().to_str().unwrap();
+    let name = dir.path().file_name().unwrap();
+    Command::cargo_bin(PRG)?
+        .args([root, "-name", "small*", "-printf", "%f %s %d|%-6d|%4y|\\n"])
+        .assert()
+        .success()
+        .stdout("small.txt 100 1|1     |   f|\n");
+    Command::cargo_bin(PRG)?
+        .args([root, "-maxdepth", "0", "-printf", "%y\\t%.3f\\0%%\\101\\c!"])
+        .assert()
+        .success()
+        .stdout(format!("d\t{}\0%A", &name.to_str().unwrap()[..3]));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn printf_path_parts() -> TestResult {
+    let dir = nested_dirs()?;
+    run_in(
+        dir.path(),
+        &["-name", "low.txt", "-printf", "%h %f\\n"],
+        &["/a/b low.txt"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn printf_mode_and_link() -> TestResult {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = sized_files()?;
+    let small = dir.path().join("small.txt");
+    fs::set_permissions(&small, fs::Permissions::from_mode(0o4750))?;
+    std::os::unix::fs::symlink("small.txt", dir.path().join("link"))?;
+    run_in(
+        dir.path(),
+        &["-name", "[ls][mi]*", "-printf", "%f %m %M %l\\n"],
+        &["link 777 lrwxrwxrwx small.txt", "small.txt 4750 -rwsr-x--- "],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn printf_time() -> TestResult {
+    let dir = aged_files()?;
+    let old = fs::metadata(dir.path().join("old.txt"))?.modified()?;
+    let since = old.duration_since(std::time::UNIX_EPOCH)?;
+    run_in(
+        dir.path(),
+        &["-name", "old.txt", "-printf", "%T@\\n"],
+        &[&format!("{}.{:09}0", since.as_secs(), since.subsec_nanos())],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_printf() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-printf", "%z"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid -printf directive \"%z\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[cfg(unix)]
+fn permed_files() -> Result<tempfile::TempDir, std::io::Error> {
+    use std::os::unix::fs::PermissionsExt;
+    let dir = tempfile::tempdir()?;
+    for (name, mode) in [("a", 0o644), ("b", 0o755), ("c", 0o600)] {
+        let path = dir.path().join(name);
+        fs::write(&path, "")?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(dir)
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn perm_exact() -> TestResult {
+    let dir = permed_files()?;
+    run_in(dir.path(), &["-type", "f", "-perm", "644"], &["/a"])?;
+    run_in(dir.path(), &["-type", "f", "-perm", "u=rw,go=r"], &["/a"])
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn perm_all() -> TestResult {
+    let dir = permed_files()?;
+    run_in(dir.path(), &["-type", "f", "-perm", "-044"], &["/a", "/b"])?;
+    run_in(dir.path(), &["-type", "f", "-perm", "-u+x"], &["/b"])
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn perm_any() -> TestResult {
+    let dir = permed_files()?;
+    run_in(dir.path(), &["-type", "f", "-perm", "/011"], &["/b"])?;
+    run_in(dir.path(), &["-type", "f", "!", "-perm", "/go=r"], &["/c"])
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn uid_and_user() -> TestResult {
+    use std::os::unix::fs::MetadataExt;
+    let dir = permed_files()?;
+    let uid = fs::metadata(dir.path().join("a"))?.uid().to_string();
+    run_in(dir.path(), &["-type", "f", "-uid", &uid], &["/a", "/b", "/c"])?;
+    run_in(dir.path(), &["-type", "f", "-uid", &format!("+{}", uid)], &[])?;
+    run_in(dir.path(), &["-type", "f", "-user", &uid], &["/a", "/b", "/c"])?;
+    run_in(dir.path(), &["-nouser"], &[])
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_bad_perm() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-perm", "u+q"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid mode \"u+q\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_unknown_user() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["-user", "no_such_user_findr"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "unknown user \"no_such_user_findr\"",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn fails_after_walk_error() -> TestResult {
+    let dir = sized_files()?;
+    let bad = gen_bad_file();
+    for jobs in ["1", "2"] {
+        Command::cargo_bin(PRG)?
+            .args(["-j", jobs])
+            .arg(&bad)
+            .arg(dir.path())
+            .args(["-name", "small.txt"])
+            .assert()
+            .failure()
+            .stdout(predicate::str::ends_with("small.txt\n"))
+            .stderr(predicate::str::contains(&bad));
+    }
+    Ok(())
+}
+
+// --------------------------------------------------
+fn duplicate_files() -> Result<tempfile::TempDir, std::io::Error> {
+    let dir = tempfile::tempdir()?;
+    let big = "x".repeat(10_000);
+    fs::create_dir(dir.path().join("sub"))?;
+    fs::write(dir.path().join("a.txt"), "same")?;
+    fs::write(dir.path().join("sub/b.txt"), "same")?;
+    fs::write(dir.path().join("c.txt"), "diff")?;
+    fs::write(dir.path().join("big1"), &big)?;
+    fs::write(dir.path().join("big2"), &big)?;
+    fs::write(dir.path().join("big3"), big.replacen('x', "y", 1))?;
+    Ok(dir)
+}
+
+// --------------------------------------------------
+fn duplicates_in(
+    dir: &Path,
+    args: &[&str],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let root = dir.to_str().unwrap();
+    let mut all = vec!["--sort", "--duplicates", root];
+    all.extend(args);
+    Ok(stdout_of(&all)?.replace(root, ""))
+}
+
+// --------------------------------------------------
+#[test]
+fn duplicates() -> TestResult {
+    let dir = duplicate_files()?;
+    assert_eq!(
+        duplicates_in(dir.path(), &[])?,
+        "/a.txt\n/sub/b.txt\n\n/big1\n/big2\n"
+    );
+    assert_eq!(
+        duplicates_in(dir.path(), &["-name", "*.txt"])?,
+        "/a.txt\n/sub/b.txt\n"
+    );
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn duplicates_dry_run() -> TestResult {
+    let dir = duplicate_files()?;
+    assert_eq!(
+        duplicates_in(dir.path(), &["--delete-but-first"])?,
+        "/a.txt\n/sub/b.txt (would delete)\n\n/big1\n/big2 (would delete)\n"
+    );
+    assert!(dir.path().join("big2").exists());
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn duplicates_delete() -> TestResult {
+    let dir = duplicate_files()?;
+    assert_eq!(
+        duplicates_in(dir.path(), &["--delete-but-first", "--force"])?,
+        "/a.txt\n/sub/b.txt (deleted)\n\n/big1\n/big2 (deleted)\n"
+    );
+    run_in(
+        dir.path(),
+        &["-type", "f"],
+        &["/a.txt", "/c.txt", "/big1", "/big3"],
+    )
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn duplicates_hardlink() -> TestResult {
+    use std::os::unix::fs::MetadataExt;
+    let dir = duplicate_files()?;
+    assert_eq!(
+        duplicates_in(dir.path(), &["--hardlink", "--force", "-size", "+1"])?,
+        "/big1\n/big2 (linked)\n"
+    );
+    let inode = |name| fs::metadata(dir.path().join(name)).map(|m| m.ino());
+    assert_eq!(inode("big1")?, inode("big2")?);
+    assert_eq!(fs::read_to_string(dir.path().join("big2"))?.len(), 10_000);
+    assert_eq!(duplicates_in(dir.path(), &["-size", "+1"])?, "");
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_duplicates_with_action() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--duplicates", "-print"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--duplicates cannot be used with actions",
+        ));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn dies_invalid_utf8() -> TestResult {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+    let bad = OsStr::from_bytes(b"tests/inputs/\xff");
+    Command::cargo_bin(PRG)?
+        .arg(bad)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid UTF-8 was detected"));
+    Command::cargo_bin(PRG)?
+        .args([OsStr::new("-name"), OsStr::from_bytes(b"\xff")])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid UTF-8 in"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+#[cfg(unix)]
+fn streams_matches() -> TestResult {
+    use std::{io::BufRead, os::unix::fs::symlink, sync::mpsc, time::Duration};
+    // Each level links twice to the next, so following the links makes a
+    // walk with millions of entries after the one match
+    let dir = tempfile::tempdir()?;
+    fs::write(dir.path().join("aaa.txt"), "")?;
+    for level in 0..24 {
+        let level_dir = dir.path().join(format!("levels/{}", level));
+        fs::create_dir_all(&level_dir)?;
+        symlink(format!("../{}", level + 1), level_dir.join("x"))?;
+        symlink(format!("../{}", level + 1), level_dir.join("y"))?;
+    }
+    fs::create_dir_all(dir.path().join("levels/24"))?;
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin(PRG))
+        .arg("--sort")
+        .arg(dir.path())
+        .args(["-L", "-name", "aaa.txt"])
+        .stdout(std::process::Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        let _ = std::io::BufReader::new(stdout).read_line(&mut line);
+        let _ = tx.send(line);
+    });
+    let line = rx.recv_timeout(Duration::from_secs(10));
+    let running = child.try_wait()?.is_none();
+    child.kill()?;
+    child.wait()?;
+    assert!(line?.ends_with("aaa.txt\n"));
+    assert!(running);
+    Ok(())
+}
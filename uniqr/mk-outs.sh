@@ -20,3 +20,31 @@ for FILE in $ROOT/*.txt; do
     uniq    < $FILE > ${OUT_DIR}/${BASENAME}.stdin.out
     uniq -c < $FILE > ${OUT_DIR}/${BASENAME}.stdin.c.out
 done
+
+# Selection and grouping modes
+FILE="$ROOT/three.txt"
+uniq -d                      $FILE > $OUT_DIR/three.txt.d.out
+uniq -u                      $FILE > $OUT_DIR/three.txt.u.out
+uniq -du                     $FILE > $OUT_DIR/three.txt.du.out
+uniq -D                      $FILE > $OUT_DIR/three.txt.D.out
+uniq --all-repeated=prepend  $FILE > $OUT_DIR/three.txt.D_prepend.out
+uniq --all-repeated=separate $FILE > $OUT_DIR/three.txt.D_separate.out
+uniq --group                 $FILE > $OUT_DIR/three.txt.group.out
+uniq --group=prepend         $FILE > $OUT_DIR/three.txt.group_prepend.out
+uniq --group=append          $FILE > $OUT_DIR/three.txt.group_append.out
+uniq --group=both            $FILE > $OUT_DIR/three.txt.group_both.out
+
+# Comparison controls
+FILE="$ROOT/log.txt"
+uniq -i                      $FILE > $OUT_DIR/log.txt.i.out
+uniq -f 2                    $FILE > $OUT_DIR/log.txt.f2.out
+uniq -f 2 -i                 $FILE > $OUT_DIR/log.txt.f2.i.out
+uniq -s 11                   $FILE > $OUT_DIR/log.txt.s11.out
+uniq -w 10                   $FILE > $OUT_DIR/log.txt.w10.out
+uniq -f 2 -w 4               $FILE > $OUT_DIR/log.txt.f2.w4.out
+uniq -f 1 -s 6 -w 2          $FILE > $OUT_DIR/log.txt.f1.s6.w2.out
+
+# Global deduplication (first-seen order)
+FILE="$ROOT/unsorted.txt"
+awk '!seen[$0]++'            $FILE > $OUT_DIR/unsorted.txt.global.out
+awk '!seen[tolower($0)]++'   $FILE > $OUT_DIR/unsorted.txt.global.i.out
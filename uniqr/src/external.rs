@@ -0,0 +1,249 @@
+use crate::{Config, Dedup, MyResult, Run};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::mem;
+
+/// Number of partitions a spilled input is split into at each level
+const FANOUT: usize = 16;
+
+/// Deepest level of re-partitioning before a partition is deduplicated
+/// in memory regardless of its size
+const MAX_DEPTH: usize = 4;
+
+/// Deduplicates `file` across the whole input while holding roughly no
+/// more than `memory_limit` bytes of lines in memory at once.
+///
+/// Lines are deduplicated in memory until the limit is reached, after which
+/// everything is spilled to temporary partition files by the hash of its
+/// key. Each partition is deduplicated on its own (partitioning it again if
+/// it is still too large) and the sorted results are merged back together,
+/// so the output is the same as the in-memory `--global` mode.
+pub fn dedup(
+    config: &Config,
+    mut file: impl BufRead,
+    memory_limit: usize,
+    print: &mut impl FnMut(&Run) -> MyResult<()>,
+) -> MyResult<()> {
+    let mut dedup = Dedup::default();
+    let mut partitions: Option<Vec<Partition>> = None;
+    let mut line = vec![];
+    let mut line_num = 0;
+    loop {
+        let bytes = file.read_until(config.terminator(), &mut line)?;
+        if bytes == 0 {
+            break;
+        }
+        line_num += 1;
+        let run = Run::new(mem::take(&mut line), line_num);
+        match &mut partitions {
+            Some(partitions) => {
+                partitions[bucket(config, &run.line, 0)].write(&run)?;
+            }
+            None => {
+                dedup.add(config, run);
+                if dedup.size > memory_limit {
+                    let mut spilled = Partition::split()?;
+                    for run in mem::take(&mut dedup).runs {
+                        spilled[bucket(config, &run.line, 0)].write(&run)?;
+                    }
+                    partitions = Some(spilled);
+                }
+            }
+        }
+    }
+
+    let Some(partitions) = partitions else {
+        for run in dedup.into_runs(config) {
+            print(&run)?;
+        }
+        return Ok(());
+    };
+
+    let mut results = vec![];
+    for partition in partitions {
+        results.extend(dedup_partition(config, partition, 1, memory_limit)?);
+    }
+    merge(config, results, print)
+}
+
+/// Deduplicates one partition into a file of runs in output order, or
+/// splits it further if it does not fit in memory and merges the results
+/// of the parts back into one file, so only a few files are open at once
+fn dedup_partition(
+    config: &Config,
+    partition: Partition,
+    depth: usize,
+    memory_limit: usize,
+) -> MyResult<Option<Partition>> {
+    if partition.is_empty() {
+        return Ok(None);
+    }
+
+    let mut dedup = Dedup::default();
+    let mut reader = partition.into_reader()?;
+    while let Some(run) = read_run(&mut reader)? {
+        dedup.add(config, run);
+        if dedup.size > memory_limit
+            && dedup.runs.len() > 1
+            && depth < MAX_DEPTH
+        {
+            drop(dedup);
+            reader.rewind()?;
+            let mut split = Partition::split()?;
+            while let Some(run) = read_run(&mut reader)? {
+                split[bucket(config, &run.line, depth)].write(&run)?;
+            }
+            drop(reader);
+
+            let mut results = vec![];
+            for partition in split {
+                results.extend(dedup_partition(
+                    config,
+                    partition,
+                    depth + 1,
+                    memory_limit,
+                )?);
+            }
+            let mut result = Partition::new()?;
+            merge(config, results, &mut |run| result.write(run))?;
+            return Ok(Some(result));
+        }
+    }
+
+    let mut result = Partition::new()?;
+    for run in dedup.into_runs(config) {
+        result.write(&run)?;
+    }
+    Ok(Some(result))
+}
+
+/// Passes the runs of all deduplicated partitions to `print` in output
+/// order
+fn merge(
+    config: &Config,
+    results: Vec<Partition>,
+    print: &mut impl FnMut(&Run) -> MyResult<()>,
+) -> MyResult<()> {
+    let order = |run: &Run| {
+        let count = if config.sort_by_count { run.count } else { 0 };
+        Reverse((Reverse(count), run.first_line))
+    };
+
+    let mut readers = results
+        .into_iter()
+        .map(Partition::into_reader)
+        .collect::<MyResult<Vec<_>>>()?;
+    let mut heads: Vec<Option<Run>> = vec![];
+    let mut heap = BinaryHeap::new();
+    for (i, reader) in readers.iter_mut().enumerate() {
+        let head = read_run(reader)?;
+        if let Some(run) = &head {
+            heap.push((order(run), i));
+        }
+        heads.push(head);
+    }
+
+    while let Some((_, i)) = heap.pop() {
+        if let Some(run) = heads[i].take() {
+            print(&run)?;
+        }
+        heads[i] = read_run(&mut readers[i])?;
+        if let Some(run) = &heads[i] {
+            heap.push((order(run), i));
+        }
+    }
+    Ok(())
+}
+
+/// The partition a line's key falls into at a given depth
+fn bucket(config: &Config, line: &[u8], depth: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    depth.hash(&mut hasher);
+    config.hash_key(line).hash(&mut hasher);
+    (hasher.finish() % FANOUT as u64) as usize
+}
+
+/// A temporary file of serialized runs
+struct Partition {
+    writer: BufWriter<File>,
+    written: bool,
+}
+
+impl Partition {
+    fn new() -> MyResult<Self> {
+        Ok(Partition {
+            writer: BufWriter::new(tempfile::tempfile()?),
+            written: false,
+        })
+    }
+
+    fn split() -> MyResult<Vec<Self>> {
+        (0..FANOUT).map(|_| Partition::new()).collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        !self.written
+    }
+
+    fn write(&mut self, run: &Run) -> MyResult<()> {
+        write_run(&mut self.writer, run)?;
+        self.written = true;
+        Ok(())
+    }
+
+    /// A reader from the start of the partition, which keeps its file open
+    fn into_reader(self) -> MyResult<BufReader<File>> {
+        let mut file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.rewind()?;
+        Ok(BufReader::new(file))
+    }
+}
+
+fn write_run(writer: &mut impl Write, run: &Run) -> io::Result<()> {
+    let lines = 1 + run.repeats.len();
+    for n in [run.first_line, run.last_line, run.count, lines] {
+        writer.write_all(&(n as u64).to_le_bytes())?;
+    }
+    for line in [&run.line].into_iter().chain(&run.repeats) {
+        writer.write_all(&(line.len() as u64).to_le_bytes())?;
+        writer.write_all(line)?;
+    }
+    Ok(())
+}
+
+fn read_run(reader: &mut impl Read) -> MyResult<Option<Run>> {
+    let first_line = match read_usize(reader) {
+        Ok(n) => n,
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            return Ok(None)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let last_line = read_usize(reader)?;
+    let count = read_usize(reader)?;
+    let mut lines = (0..read_usize(reader)?)
+        .map(|_| {
+            let mut line = vec![0; read_usize(reader)?];
+            reader.read_exact(&mut line)?;
+            Ok(line)
+        })
+        .collect::<MyResult<Vec<_>>>()?;
+    let line = lines.remove(0);
+    Ok(Some(Run {
+        line,
+        count,
+        repeats: lines,
+        first_line,
+        last_line,
+    }))
+}
+
+fn read_usize(reader: &mut impl Read) -> io::Result<usize> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf) as usize)
+}
@@ -1,10 +1,48 @@
-use clap::Parser;
+mod external;
+
+use clap::{builder::NonEmptyStringValueParser, Parser, ValueEnum};
+use csv::WriterBuilder;
+use regex::bytes::Regex;
+use std::cmp::Reverse;
+use std::collections::HashMap;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Write};
+use std::mem;
+use std::str;
+use std::num::NonZeroUsize;
+use std::path::Path;
 
 type MyResult<T> = Result<T, Box<dyn Error>>;
 
+/// How `--all-repeated` delimits groups of duplicate lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AllRepeated {
+    None,
+    Prepend,
+    Separate,
+}
+
+/// How `--group` delimits groups of lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Group {
+    Separate,
+    Prepend,
+    Append,
+    Both,
+}
+
+/// How each printed run is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// The line, preceded by its count with -c
+    Text,
+    /// One JSON object per line
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
 /// Rust uniq
 #[derive(Parser, Debug)]
 pub struct Config {
@@ -16,9 +54,355 @@ pub struct Config {
     #[arg(name = "OUT_FILE")]
     out_file: Option<String>,
 
+    /// Rewrite IN_FILE with the result
+    #[arg(long, conflicts_with = "OUT_FILE")]
+    in_place: bool,
+
     /// Show counts
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "all_repeated")]
     count: bool,
+
+    /// Only print duplicate lines, one for each group
+    #[arg(short = 'd', long)]
+    repeated: bool,
+
+    /// Only print unique lines
+    #[arg(short, long)]
+    unique: bool,
+
+    /// Print all duplicate lines
+    #[arg(
+        short = 'D',
+        long,
+        value_name = "METHOD",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "none"
+    )]
+    all_repeated: Option<AllRepeated>,
+
+    /// Show all lines, separating groups with an empty line
+    #[arg(
+        long,
+        value_name = "METHOD",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "separate",
+        conflicts_with_all = ["count", "repeated", "unique", "all_repeated"]
+    )]
+    group: Option<Group>,
+
+    /// Ignore differences in case when comparing
+    #[arg(short, long)]
+    ignore_case: bool,
+
+    /// Avoid comparing the first N fields
+    #[arg(short = 'f', long, value_name = "N", default_value_t = 0)]
+    skip_fields: usize,
+
+    /// Avoid comparing the first N characters
+    #[arg(short, long, value_name = "N", default_value_t = 0)]
+    skip_chars: usize,
+
+    /// Compare no more than N characters in lines
+    #[arg(short = 'w', long, value_name = "N")]
+    check_chars: Option<usize>,
+
+    /// Compare the first capture group of REGEX (or the whole match)
+    /// instead of the line; lines it does not match are compared whole
+    #[arg(
+        long,
+        value_name = "REGEX",
+        conflicts_with_all = ["skip_fields", "skip_chars", "check_chars", "key_field"]
+    )]
+    key_regex: Option<Regex>,
+
+    /// Compare field N instead of the line; missing fields are empty
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["skip_fields", "skip_chars", "check_chars"]
+    )]
+    key_field: Option<NonZeroUsize>,
+
+    /// Field delimiter for --key-field [default: runs of whitespace]
+    #[arg(
+        short = 't',
+        long,
+        value_name = "DELIM",
+        value_parser = NonEmptyStringValueParser::new(),
+        requires = "key_field"
+    )]
+    delimiter: Option<String>,
+
+    /// Line delimiter is NUL, not newline
+    #[arg(short, long)]
+    zero_terminated: bool,
+
+    /// Output format; json and csv give the count, line and first and
+    /// last line numbers of each run, and json needs UTF-8 lines
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "text",
+        conflicts_with_all = ["all_repeated", "group"]
+    )]
+    format: Format,
+
+    /// Deduplicate across the whole input, not just adjacent lines
+    #[arg(long)]
+    global: bool,
+
+    /// Print groups by descending count (with --global)
+    #[arg(long, requires = "global")]
+    sort_by_count: bool,
+
+    /// Spill to temporary files beyond SIZE bytes (with --global)
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        requires = "global"
+    )]
+    memory_limit: Option<usize>,
+}
+
+/// A group of lines that compare equal
+#[derive(Debug, Default)]
+struct Run {
+    /// The first line of the group, which is the one printed
+    line: Vec<u8>,
+    count: usize,
+    /// The other lines, kept only when every line is printed
+    repeats: Vec<Vec<u8>>,
+    /// The line number of `line`
+    first_line: usize,
+    /// The line number of the last line in the group
+    last_line: usize,
+}
+
+/// Approximate bookkeeping cost of a run or line beyond its text
+const OVERHEAD: usize = 64;
+
+impl Run {
+    fn new(line: Vec<u8>, first_line: usize) -> Self {
+        Run {
+            line,
+            count: 1,
+            repeats: vec![],
+            first_line,
+            last_line: first_line,
+        }
+    }
+
+    /// Approximate number of bytes the run takes up in memory
+    fn size(&self) -> usize {
+        let repeats: usize =
+            self.repeats.iter().map(|line| line.len() + OVERHEAD).sum();
+        self.line.len() + OVERHEAD + repeats
+    }
+
+    /// Adds the lines of a later run with the same key
+    fn absorb(&mut self, other: Run, keep_lines: bool) {
+        self.count += other.count;
+        self.last_line = other.last_line;
+        if keep_lines {
+            self.repeats.push(other.line);
+            self.repeats.extend(other.repeats);
+        }
+    }
+}
+
+/// Runs of equal lines across the whole input, in first-seen order
+#[derive(Debug, Default)]
+struct Dedup {
+    runs: Vec<Run>,
+    seen: HashMap<Vec<u8>, usize>,
+    /// Approximate number of bytes held
+    size: usize,
+}
+
+impl Dedup {
+    fn add(&mut self, config: &Config, run: Run) {
+        let key = config.hash_key(&run.line);
+        match self.seen.get(&key) {
+            Some(&i) => {
+                let prev = &mut self.runs[i];
+                self.size -= prev.size();
+                prev.absorb(run, config.prints_all());
+                self.size += prev.size();
+            }
+            None => {
+                self.size += key.len() + run.size();
+                self.seen.insert(key, self.runs.len());
+                self.runs.push(run);
+            }
+        }
+    }
+
+    /// The runs in output order
+    fn into_runs(self, config: &Config) -> Vec<Run> {
+        let mut runs = self.runs;
+        if config.sort_by_count {
+            runs.sort_by_key(|run| Reverse(run.count));
+        }
+        runs
+    }
+}
+
+impl Config {
+    /// Whether a run of `count` identical lines should be printed
+    fn selects(&self, count: usize) -> bool {
+        let repeated = self.repeated || self.all_repeated.is_some();
+        (count > 1 || !repeated) && (count == 1 || !self.unique)
+    }
+
+    /// The byte that ends each line
+    fn terminator(&self) -> u8 {
+        if self.zero_terminated {
+            b'\0'
+        } else {
+            b'\n'
+        }
+    }
+
+    /// The part of a line that is compared against its neighbours
+    fn key<'a>(&self, line: &'a [u8]) -> &'a [u8] {
+        let line = line
+            .strip_suffix(&[self.terminator()])
+            .unwrap_or(line)
+            .trim_ascii_end();
+        if let Some(re) = &self.key_regex {
+            return re
+                .captures(line)
+                .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+                .map_or(line, |m| m.as_bytes());
+        }
+        if let Some(key_field) = self.key_field {
+            let i = key_field.get() - 1;
+            let field = match &self.delimiter {
+                Some(delimiter) => nth_field(line, delimiter.as_bytes(), i),
+                None => line
+                    .split(u8::is_ascii_whitespace)
+                    .filter(|field| !field.is_empty())
+                    .nth(i),
+            };
+            return field.unwrap_or_default();
+        }
+
+        let is_blank = |b: &u8| *b == b' ' || *b == b'\t';
+        let mut key = line;
+        for _ in 0..self.skip_fields {
+            let start = key.iter().position(|b| !is_blank(b));
+            key = &key[start.unwrap_or(key.len())..];
+            let end = key.iter().position(is_blank);
+            key = &key[end.unwrap_or(key.len())..];
+        }
+        key = &key[char_offset(key, self.skip_chars)..];
+        if let Some(check_chars) = self.check_chars {
+            key = &key[..char_offset(key, check_chars)];
+        }
+        key
+    }
+
+    /// Whether two lines belong to the same run
+    fn same(&self, line: &[u8], other: &[u8]) -> bool {
+        if !self.ignore_case {
+            return self.key(line) == self.key(other);
+        }
+        // Folded as for hashing, so --global finds the same runs
+        self.hash_key(line) == self.hash_key(other)
+    }
+
+    /// The key of a line for hashing, folded to lowercase if need be
+    fn hash_key(&self, line: &[u8]) -> Vec<u8> {
+        let key = self.key(line);
+        if !self.ignore_case {
+            return key.to_vec();
+        }
+        match str::from_utf8(key) {
+            Ok(key) => key.to_lowercase().into_bytes(),
+            Err(_) => key.to_ascii_lowercase(),
+        }
+    }
+
+    /// Whether every line of a run is printed rather than just the first
+    fn prints_all(&self) -> bool {
+        self.all_repeated.is_some() || self.group.is_some()
+    }
+
+    /// Whether an empty line goes before the group after `groups` others
+    fn delimits_before(&self, groups: usize) -> bool {
+        match (self.all_repeated, self.group) {
+            (Some(AllRepeated::Prepend), _)
+            | (_, Some(Group::Prepend | Group::Both)) => true,
+            (Some(AllRepeated::Separate), _)
+            | (_, Some(Group::Separate)) => groups > 0,
+            _ => false,
+        }
+    }
+}
+
+/// The byte offset of the `n`th character, counting bytes if the text is
+/// not valid UTF-8
+fn char_offset(text: &[u8], n: usize) -> usize {
+    match str::from_utf8(text) {
+        Ok(text) => text.char_indices().nth(n).map_or(text.len(), |(i, _)| i),
+        Err(_) => n.min(text.len()),
+    }
+}
+
+/// The `n`th (0-based) field of `line` split on `delimiter`
+fn nth_field<'a>(line: &'a [u8], delimiter: &[u8], n: usize) -> Option<&'a [u8]> {
+    let find = |text: &[u8]| {
+        text.windows(delimiter.len()).position(|window| window == delimiter)
+    };
+    let mut rest = line;
+    for _ in 0..n {
+        rest = &rest[find(rest)? + delimiter.len()..];
+    }
+    Some(&rest[..find(rest).unwrap_or(rest.len())])
+}
+
+/// Whether two paths name the same file, following symlinks
+#[cfg(unix)]
+fn same_file(path: &str, other: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(path), fs::metadata(other)) {
+        (Ok(meta), Ok(other)) => {
+            meta.dev() == other.dev() && meta.ino() == other.ino()
+        }
+        _ => false,
+    }
+}
+
+/// Whether two paths name the same file, following symlinks
+#[cfg(not(unix))]
+fn same_file(path: &str, other: &str) -> bool {
+    match (fs::canonicalize(path), fs::canonicalize(other)) {
+        (Ok(path), Ok(other)) => path == other,
+        _ => false,
+    }
+}
+
+/// A temporary file next to `path` that can replace it once written
+fn in_place_file(path: &Path) -> MyResult<tempfile::NamedTempFile> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file = tempfile::NamedTempFile::new_in(dir)?;
+    file.as_file().set_permissions(fs::metadata(path)?.permissions())?;
+    Ok(file)
+}
+
+/// Writes a single CSV record
+fn write_csv<I, T>(out: &mut impl Write, record: I) -> MyResult<()>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut wtr = WriterBuilder::new().from_writer(out);
+    wtr.write_record(record)?;
+    wtr.flush()?;
+    Ok(())
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -32,41 +416,183 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (digits, unit) = match size.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&size[..i], Some(c)),
+        _ => (size, None),
+    };
+    let multiplier: usize = match unit.map(|c| c.to_ascii_uppercase()) {
+        None => 1,
+        Some('K') => 1 << 10,
+        Some('M') => 1 << 20,
+        Some('G') => 1 << 30,
+        Some(_) => return Err(format!("invalid size \"{}\"", size)),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("invalid size \"{}\"", size))
+}
+
+fn read_global(config: &Config, mut file: impl BufRead) -> MyResult<Vec<Run>> {
+    let mut dedup = Dedup::default();
+    let mut line = vec![];
+    let mut line_num = 0;
+    loop {
+        let bytes = file.read_until(config.terminator(), &mut line)?;
+        if bytes == 0 {
+            break;
+        }
+        line_num += 1;
+        dedup.add(config, Run::new(mem::take(&mut line), line_num));
+    }
+    Ok(dedup.into_runs(config))
+}
+
 pub fn run(config: Config) -> MyResult<()> {
+    if config.in_place && config.in_file == "-" {
+        return Err(From::from("--in-place requires an IN_FILE"));
+    }
+    if let Some(out_file) = &config.out_file {
+        if config.in_file != "-" && same_file(&config.in_file, out_file) {
+            return Err(From::from(format!(
+                "{}: input and output are the same file (use --in-place)",
+                out_file
+            )));
+        }
+    }
+
     let mut file = open(&config.in_file)
         .map_err(|err| format!("{}: {}", config.in_file, err))?;
-    let mut line = String::new();
-    let mut prev_line = String::new();
-    let mut count = 0;
-    let mut out_file: Box<dyn Write> = match &config.out_file {
-        Some(filename) => Box::new(File::create(filename)?),
-        None => Box::new(io::stdout()),
+    let mut groups = 0;
+    let mut in_place = None;
+    let mut out_file: Box<dyn Write> = if config.in_place {
+        // Resolve symlinks so the file they point to is replaced
+        let target = fs::canonicalize(&config.in_file)?;
+        let tmp = in_place_file(&target)?;
+        let out_file = tmp.reopen()?;
+        in_place = Some((tmp, target));
+        Box::new(out_file)
+    } else {
+        match &config.out_file {
+            Some(filename) => Box::new(File::create(filename)?),
+            None => Box::new(io::stdout()),
+        }
     };
 
-    let mut print = |count: usize, line: &str| -> MyResult<()> {
-        if count > 0 {
-            if config.count {
-                write!(out_file, "{:>4} {}", count, line)?;
-            } else {
-                write!(out_file, "{}", line)?;
+    if config.format == Format::Csv {
+        write_csv(
+            &mut out_file,
+            ["count", "line", "first_line_number", "last_line_number"],
+        )?;
+    }
+
+    // Whether the last line written had no terminator, as the last line of
+    // the input may not, which only stays that way if nothing follows it
+    let mut unterminated = false;
+    let mut print = |run: &Run| -> MyResult<()> {
+        if run.count == 0 || !config.selects(run.count) {
+            return Ok(());
+        }
+        let line = run.line.strip_suffix(&[config.terminator()]);
+        let line = line.unwrap_or(&run.line);
+        match config.format {
+            Format::Text => {
+                if unterminated {
+                    out_file.write_all(&[config.terminator()])?;
+                }
+                if config.delimits_before(groups) {
+                    out_file.write_all(&[config.terminator()])?;
+                }
+                if config.count {
+                    write!(out_file, "{:>4} ", run.count)?;
+                }
+                out_file.write_all(&run.line)?;
+                for repeat in &run.repeats {
+                    out_file.write_all(repeat)?;
+                }
+                let last = run.repeats.last().unwrap_or(&run.line);
+                unterminated = !last.ends_with(&[config.terminator()]);
+                if config.group == Some(Group::Append) {
+                    if mem::take(&mut unterminated) {
+                        out_file.write_all(&[config.terminator()])?;
+                    }
+                    out_file.write_all(&[config.terminator()])?;
+                }
+            }
+            Format::Json => {
+                let line = str::from_utf8(line).map_err(|_| {
+                    format!(
+                        "line {}: invalid UTF-8 (use --format csv)",
+                        run.first_line
+                    )
+                })?;
+                let record = serde_json::json!({
+                    "count": run.count,
+                    "line": line,
+                    "first_line_number": run.first_line,
+                    "last_line_number": run.last_line,
+                });
+                writeln!(out_file, "{}", record)?;
             }
-        };
+            Format::Csv => {
+                write_csv(
+                    &mut out_file,
+                    [
+                        run.count.to_string().as_bytes(),
+                        line,
+                        run.first_line.to_string().as_bytes(),
+                        run.last_line.to_string().as_bytes(),
+                    ],
+                )?;
+            }
+        }
+        groups += 1;
         Ok(())
     };
 
-    loop {
-        let bytes = file.read_line(&mut line)?;
-        if bytes == 0 {
-            break;
+    if let Some(memory_limit) = config.memory_limit {
+        external::dedup(&config, file, memory_limit, &mut print)?;
+    } else if config.global {
+        for run in read_global(&config, file)? {
+            print(&run)?;
         }
-        if line.trim_end() != prev_line.trim_end() {
-            print(count, &prev_line)?;
-            prev_line = line.clone();
-            count = 0;
+    } else {
+        let mut line = vec![];
+        let mut line_num = 0;
+        let mut run = Run::default();
+        loop {
+            let bytes = file.read_until(config.terminator(), &mut line)?;
+            if bytes == 0 {
+                break;
+            }
+            line_num += 1;
+            if run.count == 0 || !config.same(&line, &run.line) {
+                print(&run)?;
+                run = Run::new(mem::take(&mut line), line_num);
+            } else {
+                run.count += 1;
+                run.last_line = line_num;
+                if config.prints_all() {
+                    run.repeats.push(mem::take(&mut line));
+                }
+            }
+            line.clear();
         }
-        count += 1;
-        line.clear();
+        print(&run)?;
+    }
+    if config.group == Some(Group::Both) && groups > 0 {
+        if unterminated {
+            out_file.write_all(&[config.terminator()])?;
+        }
+        out_file.write_all(&[config.terminator()])?;
+    }
+    out_file.flush()?;
+    if let Some((tmp, target)) = in_place {
+        drop(out_file);
+        tmp.persist(target)?;
     }
-    print(count, &prev_line)?;
     Ok(())
 }
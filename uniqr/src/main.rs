@@ -1,5 +1,6 @@
 fn main() {
     if let Err(err) = uniqr::get_args().and_then(uniqr::run) {
         eprintln!("Error: {}", err);
+        std::process::exit(1);
     }
 }
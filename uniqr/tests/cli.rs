@@ -2,7 +2,7 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 use rand::{distributions::Alphanumeric, Rng};
 use std::fs;
-use tempfile::NamedTempFile;
+use tempfile::{tempdir, NamedTempFile};
 
 type TestResult = Result<(), Box<dyn std::error::Error>>;
 
@@ -605,3 +605,607 @@ fn t6_outfile_count() -> TestResult {
 fn t6_stdin_outfile_count() -> TestResult {
     run_stdin_outfile_count(&T6)
 }
+
+// --------------------------------------------------
+fn run_args(args: &[&str], expected_file: &str) -> TestResult {
+    let expected = fs::read_to_string(expected_file)?;
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn three_repeated() -> TestResult {
+    run_args(
+        &["-d", THREE.input],
+        "tests/expected/three.txt.d.out",
+    )
+}
+
+#[test]
+fn three_unique() -> TestResult {
+    run_args(
+        &["-u", THREE.input],
+        "tests/expected/three.txt.u.out",
+    )
+}
+
+#[test]
+fn three_repeated_unique() -> TestResult {
+    run_args(
+        &["-d", "-u", THREE.input],
+        "tests/expected/three.txt.du.out",
+    )
+}
+
+#[test]
+fn three_all_repeated() -> TestResult {
+    run_args(
+        &["-D", THREE.input],
+        "tests/expected/three.txt.D.out",
+    )
+}
+
+#[test]
+fn three_all_repeated_prepend() -> TestResult {
+    run_args(
+        &["--all-repeated=prepend", THREE.input],
+        "tests/expected/three.txt.D_prepend.out",
+    )
+}
+
+#[test]
+fn three_all_repeated_separate() -> TestResult {
+    run_args(
+        &["--all-repeated=separate", THREE.input],
+        "tests/expected/three.txt.D_separate.out",
+    )
+}
+
+#[test]
+fn three_group() -> TestResult {
+    run_args(
+        &["--group", THREE.input],
+        "tests/expected/three.txt.group.out",
+    )
+}
+
+#[test]
+fn three_group_prepend() -> TestResult {
+    run_args(
+        &["--group=prepend", THREE.input],
+        "tests/expected/three.txt.group_prepend.out",
+    )
+}
+
+#[test]
+fn three_group_append() -> TestResult {
+    run_args(
+        &["--group=append", THREE.input],
+        "tests/expected/three.txt.group_append.out",
+    )
+}
+
+#[test]
+fn three_group_both() -> TestResult {
+    run_args(
+        &["--group=both", THREE.input],
+        "tests/expected/three.txt.group_both.out",
+    )
+}
+
+#[test]
+fn group_append_ends_missing_final_newline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("--group=append")
+        .write_stdin("a\na")
+        .assert()
+        .success()
+        .stdout("a\na\n\n");
+    Command::cargo_bin(PRG)?
+        .arg("--group=both")
+        .write_stdin("a\nb")
+        .assert()
+        .success()
+        .stdout("\na\n\nb\n\n");
+    Ok(())
+}
+
+#[test]
+fn dies_group_with_count() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--group", "-c", THREE.input])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+const LOG: &str = "tests/inputs/log.txt";
+
+#[test]
+fn log_ignore_case() -> TestResult {
+    run_args(&["-i", LOG], "tests/expected/log.txt.i.out")
+}
+
+#[test]
+fn log_skip_fields() -> TestResult {
+    run_args(&["-f", "2", LOG], "tests/expected/log.txt.f2.out")
+}
+
+#[test]
+fn log_skip_fields_ignore_case() -> TestResult {
+    run_args(
+        &["--skip-fields", "2", "--ignore-case", LOG],
+        "tests/expected/log.txt.f2.i.out",
+    )
+}
+
+#[test]
+fn log_skip_chars() -> TestResult {
+    run_args(&["-s", "11", LOG], "tests/expected/log.txt.s11.out")
+}
+
+#[test]
+fn log_check_chars() -> TestResult {
+    run_args(&["-w", "10", LOG], "tests/expected/log.txt.w10.out")
+}
+
+#[test]
+fn log_skip_fields_check_chars() -> TestResult {
+    run_args(
+        &["-f", "2", "-w", "4", LOG],
+        "tests/expected/log.txt.f2.w4.out",
+    )
+}
+
+#[test]
+fn log_skip_fields_chars_check_chars() -> TestResult {
+    run_args(
+        &["-f", "1", "-s", "6", "-w", "2", LOG],
+        "tests/expected/log.txt.f1.s6.w2.out",
+    )
+}
+
+// --------------------------------------------------
+const UNSORTED: &str = "tests/inputs/unsorted.txt";
+
+#[test]
+fn unsorted_global() -> TestResult {
+    run_args(
+        &["--global", UNSORTED],
+        "tests/expected/unsorted.txt.global.out",
+    )
+}
+
+#[test]
+fn unsorted_global_ignore_case() -> TestResult {
+    run_args(
+        &["--global", "-i", UNSORTED],
+        "tests/expected/unsorted.txt.global.i.out",
+    )
+}
+
+#[test]
+fn unsorted_global_unique() -> TestResult {
+    run_args(
+        &["--global", "-u", UNSORTED],
+        "tests/expected/unsorted.txt.global.u.out",
+    )
+}
+
+#[test]
+fn unsorted_global_sort_by_count() -> TestResult {
+    run_args(
+        &["--global", "--sort-by-count", "-c", UNSORTED],
+        "tests/expected/unsorted.txt.global.sort.c.out",
+    )
+}
+
+#[test]
+fn dies_sort_by_count_without_global() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--sort-by-count", UNSORTED])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--global"));
+    Ok(())
+}
+
+#[test]
+fn unsorted_global_memory_limit() -> TestResult {
+    run_args(
+        &["--global", "--memory-limit", "1", UNSORTED],
+        "tests/expected/unsorted.txt.global.out",
+    )
+}
+
+#[test]
+fn unsorted_global_memory_limit_sort_by_count() -> TestResult {
+    run_args(
+        &["--global", "--memory-limit", "1", "--sort-by-count", "-c", UNSORTED],
+        "tests/expected/unsorted.txt.global.sort.c.out",
+    )
+}
+
+#[test]
+fn global_memory_limit_matches_in_memory() -> TestResult {
+    let mut rng = rand::thread_rng();
+    let input: String = (0..5000)
+        .map(|_| format!("line{}\n", rng.gen_range(0..500)))
+        .collect();
+
+    for args in [
+        vec!["--global"],
+        vec!["--global", "-c", "--sort-by-count"],
+        vec!["--global", "--all-repeated=separate"],
+    ] {
+        let cmd = Command::cargo_bin(PRG)?
+            .args(&args)
+            .write_stdin(input.clone())
+            .assert()
+            .success();
+        let expected = cmd.get_output().stdout.clone();
+
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .args(["--memory-limit", "1K"])
+            .write_stdin(input.clone())
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn global_memory_limit_keeps_few_files_open() -> TestResult {
+    // Far more input than the limit, so partitions are split to the
+    // deepest level, yet it runs with few file descriptors
+    let input: String = (0..20000).map(|i| format!("line{}\n", i)).collect();
+    let script = format!(
+        "ulimit -n 128 && exec {} --global --memory-limit 1K",
+        assert_cmd::cargo::cargo_bin(PRG).display()
+    );
+    Command::new("sh")
+        .args(["-c", &script])
+        .write_stdin(input.clone())
+        .assert()
+        .success()
+        .stdout(input);
+    Ok(())
+}
+
+#[test]
+fn dies_bad_memory_limit() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--global", "--memory-limit", "2X", UNSORTED])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid size \"2X\""));
+    Ok(())
+}
+
+// --------------------------------------------------
+const USERS: &str = "tests/inputs/users.log";
+const TEAMS: &str = "tests/inputs/teams.csv";
+
+#[test]
+fn users_key_regex_count() -> TestResult {
+    run_args(
+        &["--key-regex", r"user=(\w+)", "-c", USERS],
+        "tests/expected/users.log.key_regex.c.out",
+    )
+}
+
+#[test]
+fn users_key_regex_ignore_case() -> TestResult {
+    run_args(
+        &["--key-regex", r"user=(\w+)", "-i", USERS],
+        "tests/expected/users.log.key_regex.i.out",
+    )
+}
+
+#[test]
+fn users_key_regex_whole_match_global() -> TestResult {
+    run_args(
+        &["--key-regex", r"user=\w+", "--global", USERS],
+        "tests/expected/users.log.key_regex.global.out",
+    )
+}
+
+#[test]
+fn users_key_field_whitespace() -> TestResult {
+    run_args(
+        &["--key-field", "2", USERS],
+        "tests/expected/users.log.key_field2.out",
+    )
+}
+
+#[test]
+fn teams_key_field_delimiter_count() -> TestResult {
+    run_args(
+        &["--key-field", "3", "-t", ",", "-c", TEAMS],
+        "tests/expected/teams.csv.key_field3.c.out",
+    )
+}
+
+#[test]
+fn dies_bad_key_regex() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--key-regex", "(", USERS])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value '('"));
+    Ok(())
+}
+
+#[test]
+fn dies_key_regex_with_skip_fields() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--key-regex", "x", "-f", "1", USERS])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    Ok(())
+}
+
+// --------------------------------------------------
+fn run_bytes(args: &[&str], expected_file: &str) -> TestResult {
+    let expected = fs::read(expected_file)?;
+    Command::cargo_bin(PRG)?
+        .args(args)
+        .assert()
+        .success()
+        .stdout(expected);
+    Ok(())
+}
+
+#[test]
+fn latin1() -> TestResult {
+    run_bytes(
+        &["tests/inputs/latin1.txt"],
+        "tests/expected/latin1.txt.out",
+    )
+}
+
+#[test]
+fn latin1_count() -> TestResult {
+    run_bytes(
+        &["-c", "tests/inputs/latin1.txt"],
+        "tests/expected/latin1.txt.c.out",
+    )
+}
+
+#[test]
+fn zero_terminated() -> TestResult {
+    run_bytes(
+        &["-z", "tests/inputs/zero.txt"],
+        "tests/expected/zero.txt.z.out",
+    )
+}
+
+#[test]
+fn zero_terminated_count() -> TestResult {
+    run_bytes(
+        &["--zero-terminated", "-c", "tests/inputs/zero.txt"],
+        "tests/expected/zero.txt.z.c.out",
+    )
+}
+
+#[test]
+fn keeps_missing_final_newline() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .write_stdin("a\nb\nb")
+        .assert()
+        .success()
+        .stdout("a\nb\n");
+    Command::cargo_bin(PRG)?
+        .write_stdin("a\nb")
+        .assert()
+        .success()
+        .stdout("a\nb");
+    Ok(())
+}
+
+#[test]
+fn global_ends_missing_final_newline_mid_output() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--global", "-D"])
+        .write_stdin("a\nb\nb\na")
+        .assert()
+        .success()
+        .stdout("a\na\nb\nb\n");
+    Command::cargo_bin(PRG)?
+        .args(["--global", "--group"])
+        .write_stdin("a\nb\na")
+        .assert()
+        .success()
+        .stdout("a\na\n\nb\n");
+    Command::cargo_bin(PRG)?
+        .args(["--global", "--sort-by-count", "-c"])
+        .write_stdin("a\nb\nb\nc\nc\nc")
+        .assert()
+        .success()
+        .stdout("   3 c\n   2 b\n   1 a\n");
+    Ok(())
+}
+
+#[test]
+fn ignore_case_final_sigma() -> TestResult {
+    for args in [vec!["-i", "-c"], vec!["-i", "-c", "--global"]] {
+        Command::cargo_bin(PRG)?
+            .args(&args)
+            .write_stdin("ΣΑΣ\nσας\n")
+            .assert()
+            .success()
+            .stdout("   2 ΣΑΣ\n");
+    }
+    Ok(())
+}
+
+#[test]
+fn ignore_case_non_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("-i")
+        .write_stdin(&b"caf\xe9\nCAF\xe9\ncafe\n"[..])
+        .assert()
+        .success()
+        .stdout(&b"caf\xe9\ncafe\n"[..]);
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn dies_same_in_and_out_file() -> TestResult {
+    let dir = tempdir()?;
+    let path = dir.path().join("in.txt");
+    fs::copy(THREE.input, &path)?;
+    let path = path.to_str().unwrap();
+
+    Command::cargo_bin(PRG)?
+        .args([path, path])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("are the same file"));
+    assert_eq!(fs::read_to_string(path)?, fs::read_to_string(THREE.input)?);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn dies_out_file_symlink_to_in_file() -> TestResult {
+    let dir = tempdir()?;
+    let path = dir.path().join("in.txt");
+    let link = dir.path().join("link.txt");
+    fs::copy(THREE.input, &path)?;
+    std::os::unix::fs::symlink(&path, &link)?;
+
+    Command::cargo_bin(PRG)?
+        .args([&path, &link])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("are the same file"));
+    assert_eq!(fs::read_to_string(path)?, fs::read_to_string(THREE.input)?);
+    Ok(())
+}
+
+#[test]
+fn in_place() -> TestResult {
+    let dir = tempdir()?;
+    let path = dir.path().join("in.txt");
+    fs::copy(THREE.input, &path)?;
+
+    Command::cargo_bin(PRG)?
+        .arg("--in-place")
+        .arg(&path)
+        .assert()
+        .success()
+        .stdout("");
+    assert_eq!(fs::read_to_string(path)?, fs::read_to_string(THREE.out)?);
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn in_place_through_symlink() -> TestResult {
+    let dir = tempdir()?;
+    let path = dir.path().join("in.txt");
+    let link = dir.path().join("link.txt");
+    fs::copy(THREE.input, &path)?;
+    std::os::unix::fs::symlink(&path, &link)?;
+
+    Command::cargo_bin(PRG)?
+        .arg("--in-place")
+        .arg(&link)
+        .assert()
+        .success();
+    assert!(fs::symlink_metadata(&link)?.file_type().is_symlink());
+    assert_eq!(fs::read_to_string(path)?, fs::read_to_string(THREE.out)?);
+    Ok(())
+}
+
+#[test]
+fn dies_in_place_stdin() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .arg("--in-place")
+        .write_stdin("a\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--in-place requires an IN_FILE"));
+    Ok(())
+}
+
+// --------------------------------------------------
+#[test]
+fn three_format_json() -> TestResult {
+    run_args(
+        &["--format", "json", THREE.input],
+        "tests/expected/three.txt.json.out",
+    )
+}
+
+#[test]
+fn dies_format_json_non_utf8() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "json"])
+        .write_stdin(&b"cafe\ncaf\xe9\ncaf\xe8\n"[..])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("line 2: invalid UTF-8"));
+    Ok(())
+}
+
+#[test]
+fn three_format_csv() -> TestResult {
+    run_args(
+        &["--format", "csv", THREE.input],
+        "tests/expected/three.txt.csv.out",
+    )
+}
+
+#[test]
+fn unsorted_global_format_csv() -> TestResult {
+    run_args(
+        &["--format", "csv", "--global", UNSORTED],
+        "tests/expected/unsorted.txt.global.csv.out",
+    )
+}
+
+#[test]
+fn unsorted_global_memory_limit_format_csv() -> TestResult {
+    run_args(
+        &["--format", "csv", "--global", "--memory-limit", "1", UNSORTED],
+        "tests/expected/unsorted.txt.global.csv.out",
+    )
+}
+
+#[test]
+fn format_csv_quotes_line() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "csv"])
+        .write_stdin("a,\"b\"\na,\"b\"\n")
+        .assert()
+        .success()
+        .stdout(
+            "count,line,first_line_number,last_line_number\n\
+            2,\"a,\"\"b\"\"\",1,2\n",
+        );
+    Ok(())
+}
+
+#[test]
+fn format_csv_empty() -> TestResult {
+    Command::cargo_bin(PRG)?
+        .args(["--format", "csv", EMPTY.input])
+        .assert()
+        .success()
+        .stdout("count,line,first_line_number,last_line_number\n");
+    Ok(())
+}